@@ -0,0 +1,364 @@
+// Advisor approval of student schedules. Admins assign each student an advisor;
+// the student submits their schedule and the advisor approves or rejects it.
+// Changing the courses in a schedule sends it back to draft.
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::{AuthUser, Role};
+use super::error::{ApiError, ApiErrorBody};
+use super::events::schedule_changed;
+use super::notifications::{notify, NotificationKind};
+use super::{
+    save_schedules, save_users, schedule_of, scheduled_courses, AppState, Course, Schedule, ScheduleComment,
+    ScheduleStatus, ScheduledCourse, User,
+};
+
+// Advisor to assign to a student
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct AdvisorAssignment {
+    advisor: String,
+}
+
+// Optional comment sent when submitting or reviewing a schedule
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ReviewNote {
+    comment: Option<String>,
+}
+
+// Where a student's schedule stands in the approval workflow
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ApprovalStatus {
+    status: ScheduleStatus,
+    advisor: Option<String>,
+    comments: Vec<ScheduleComment>,
+}
+
+// One of an advisor's students and the state of their schedule
+#[derive(Serialize, Debug, ToSchema)]
+pub struct AdviseeSummary {
+    username: String,
+    status: ScheduleStatus,
+    course_count: usize,
+}
+
+// A student's schedule as seen by their advisor
+#[derive(Serialize, Debug, ToSchema)]
+pub struct AdviseeSchedule {
+    username: String,
+    status: ScheduleStatus,
+    comments: Vec<ScheduleComment>,
+    courses: Vec<(ScheduledCourse, Course)>,
+}
+
+// Returns the advisor assigned to a student, if any
+fn advisor_of(state: &AppState, username: &str) -> Option<String> {
+    let users = state.users.lock().unwrap();
+    users.iter().find(|u| u.username == username).and_then(|u| u.advisor.clone())
+}
+
+// Checks that a student exists and is advised by the given advisor
+fn check_advisee(state: &AppState, advisor: &str, student: &str) -> Result<(), ApiError> {
+    let users = state.users.lock().unwrap();
+    let user = users
+        .iter()
+        .find(|u| u.username == student && u.role == Role::Student)
+        .ok_or(ApiError::UserNotFound)?;
+    if user.advisor.as_deref() != Some(advisor) {
+        return Err(ApiError::Forbidden("This student is not one of your advisees".to_string()));
+    }
+    Ok(())
+}
+
+// Summarises every student assigned to an advisor, sorted by username
+fn advisees(state: &AppState, advisor: &str) -> Vec<AdviseeSummary> {
+    let usernames: Vec<String> = {
+        let users = state.users.lock().unwrap();
+        users
+            .iter()
+            .filter(|u| u.advisor.as_deref() == Some(advisor))
+            .map(|u| u.username.clone())
+            .collect()
+    };
+
+    let mut summaries: Vec<AdviseeSummary> = usernames
+        .into_iter()
+        .map(|username| {
+            let schedule = schedule_of(state, &username);
+            AdviseeSummary { username, status: schedule.status, course_count: schedule.courses.len() }
+        })
+        .collect();
+    summaries.sort_by(|a, b| a.username.cmp(&b.username));
+    summaries
+}
+
+// Moves a schedule to a new status, recording the comment if one was given
+fn transition(
+    state: &AppState,
+    student: &str,
+    author: &str,
+    action: &str,
+    allowed_from: &[ScheduleStatus],
+    to: ScheduleStatus,
+    comment: Option<String>,
+) -> Result<Schedule, ApiError> {
+    let mut schedules = state.schedules.lock().unwrap();
+    let schedule = schedules.entry(student.to_string()).or_default();
+    if !allowed_from.contains(&schedule.status) {
+        return Err(ApiError::ScheduleState(format!("Cannot move a {} schedule to {}", schedule.status, to)));
+    }
+
+    let previous = schedule.clone();
+    schedule.status = to;
+    if let Some(text) = comment.filter(|text| !text.trim().is_empty()) {
+        schedule.comments.push(ScheduleComment { author: author.to_string(), status: to, text });
+    }
+
+    let updated = schedule.clone();
+    if let Err(e) = save_schedules(&schedules) {
+        schedules.insert(student.to_string(), previous);
+        return Err(ApiError::storage("schedule", e));
+    }
+    audit::record(
+        state,
+        author,
+        action,
+        format!("schedule:{}", student),
+        Some(&serde_json::json!({ "status": previous.status })),
+        Some(&serde_json::json!({ "status": updated.status, "comment": updated.comments.last().map(|c| &c.text) })),
+    );
+    schedule_changed(state, &schedules, student);
+    Ok(updated)
+}
+
+// PUT /api/users/{username}/advisor
+#[utoipa::path(
+    put,
+    path = "/api/users/{username}/advisor",
+    tag = "users",
+    params(("username" = String, Path, description = "Student username")),
+    request_body = AdvisorAssignment,
+    responses(
+        (status = 200, description = "Advisor assigned", body = User),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn assign_advisor(admin: AuthUser, username: web::Path<String>, body: web::Json<AdvisorAssignment>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let advisor = body.into_inner().advisor;
+    let mut users = state.users.lock().unwrap();
+    if !users.iter().any(|u| u.username == advisor && u.role == Role::Advisor) {
+        return Err(ApiError::validation("advisor", "No advisor with that username"));
+    }
+
+    let student = users
+        .iter_mut()
+        .find(|u| u.username == *username && u.role == Role::Student)
+        .ok_or(ApiError::UserNotFound)?;
+    let before = student.clone();
+    student.advisor = Some(advisor);
+    let student = student.clone();
+
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    audit::record(&state, &admin.username, "user.assign_advisor", format!("user:{}", student.username), Some(&before), Some(&student));
+    Ok(HttpResponse::Ok().json(student))
+}
+
+// DELETE /api/users/{username}/advisor
+#[utoipa::path(
+    delete,
+    path = "/api/users/{username}/advisor",
+    tag = "users",
+    params(("username" = String, Path, description = "Student username")),
+    responses(
+        (status = 204, description = "Advisor unassigned"),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn unassign_advisor(admin: AuthUser, username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut users = state.users.lock().unwrap();
+    let student = users
+        .iter_mut()
+        .find(|u| u.username == *username && u.role == Role::Student)
+        .ok_or(ApiError::UserNotFound)?;
+    let before = student.clone();
+    student.advisor = None;
+    let student = student.clone();
+
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    audit::record(&state, &admin.username, "user.unassign_advisor", format!("user:{}", student.username), Some(&before), Some(&student));
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/me/schedule/approval
+#[utoipa::path(
+    get,
+    path = "/api/me/schedule/approval",
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Approval state of the schedule", body = ApprovalStatus),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn approval_status(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    let advisor = advisor_of(&state, &user.username);
+    let schedule = schedule_of(&state, &user.username);
+    HttpResponse::Ok().json(ApprovalStatus { status: schedule.status, advisor, comments: schedule.comments })
+}
+
+// POST /api/me/schedule/approval
+#[utoipa::path(
+    post,
+    path = "/api/me/schedule/approval",
+    tag = "schedule",
+    request_body = ReviewNote,
+    responses(
+        (status = 200, description = "Schedule submitted for approval", body = Schedule),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 409, description = "Schedule cannot be submitted in its current state", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn submit_schedule(user: AuthUser, body: web::Json<ReviewNote>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let Some(advisor) = advisor_of(&state, &user.username) else {
+        return Err(ApiError::ScheduleState("No advisor is assigned to you".to_string()));
+    };
+    if schedule_of(&state, &user.username).courses.is_empty() {
+        return Err(ApiError::ScheduleState("Cannot submit an empty schedule".to_string()));
+    }
+
+    let schedule = transition(
+        &state,
+        &user.username,
+        &user.username,
+        "schedule.submit",
+        &[ScheduleStatus::Draft, ScheduleStatus::Rejected],
+        ScheduleStatus::Submitted,
+        body.into_inner().comment,
+    )?;
+    let message = format!("{} submitted their schedule for approval", user.username);
+    notify(&state, &[advisor], NotificationKind::ScheduleSubmitted, None, &message);
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+// GET /api/me/advisees
+#[utoipa::path(
+    get,
+    path = "/api/me/advisees",
+    tag = "advisor",
+    responses(
+        (status = 200, description = "Students assigned to the logged-in advisor", body = Vec<AdviseeSummary>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_advisees(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(advisees(&state, &user.username))
+}
+
+// GET /api/me/approvals
+#[utoipa::path(
+    get,
+    path = "/api/me/approvals",
+    tag = "advisor",
+    responses(
+        (status = 200, description = "Advisees whose schedules are waiting for approval", body = Vec<AdviseeSummary>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn pending_approvals(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    let pending: Vec<AdviseeSummary> = advisees(&state, &user.username)
+        .into_iter()
+        .filter(|a| a.status == ScheduleStatus::Submitted)
+        .collect();
+    HttpResponse::Ok().json(pending)
+}
+
+// GET /api/me/advisees/{username}/schedule
+#[utoipa::path(
+    get,
+    path = "/api/me/advisees/{username}/schedule",
+    tag = "advisor",
+    params(("username" = String, Path, description = "Student username")),
+    responses(
+        (status = 200, description = "The student's schedule", body = AdviseeSchedule),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+    )
+)]
+pub async fn advisee_schedule(user: AuthUser, username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    check_advisee(&state, &user.username, &username)?;
+    let schedule = schedule_of(&state, &username);
+    Ok(HttpResponse::Ok().json(AdviseeSchedule {
+        courses: scheduled_courses(&state, &username),
+        username: username.into_inner(),
+        status: schedule.status,
+        comments: schedule.comments,
+    }))
+}
+
+// POST /api/me/advisees/{username}/schedule/approve
+#[utoipa::path(
+    post,
+    path = "/api/me/advisees/{username}/schedule/approve",
+    tag = "advisor",
+    params(("username" = String, Path, description = "Student username")),
+    request_body = ReviewNote,
+    responses(
+        (status = 200, description = "Schedule approved", body = Schedule),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 409, description = "Schedule is not waiting for approval", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn approve_schedule(user: AuthUser, username: web::Path<String>, body: web::Json<ReviewNote>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    check_advisee(&state, &user.username, &username)?;
+    let schedule = transition(
+        &state,
+        &username,
+        &user.username,
+        "schedule.approve",
+        &[ScheduleStatus::Submitted],
+        ScheduleStatus::Approved,
+        body.into_inner().comment,
+    )?;
+    let message = format!("Your schedule was approved by {}", user.username);
+    notify(&state, &[username.into_inner()], NotificationKind::ScheduleApproved, None, &message);
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+// POST /api/me/advisees/{username}/schedule/reject
+#[utoipa::path(
+    post,
+    path = "/api/me/advisees/{username}/schedule/reject",
+    tag = "advisor",
+    params(("username" = String, Path, description = "Student username")),
+    request_body = ReviewNote,
+    responses(
+        (status = 200, description = "Schedule rejected", body = Schedule),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 409, description = "Schedule is not waiting for approval", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn reject_schedule(user: AuthUser, username: web::Path<String>, body: web::Json<ReviewNote>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    check_advisee(&state, &user.username, &username)?;
+    let schedule = transition(
+        &state,
+        &username,
+        &user.username,
+        "schedule.reject",
+        &[ScheduleStatus::Submitted],
+        ScheduleStatus::Rejected,
+        body.into_inner().comment,
+    )?;
+    let message = format!("Your schedule was sent back by {}", user.username);
+    notify(&state, &[username.into_inner()], NotificationKind::ScheduleRejected, None, &message);
+    Ok(HttpResponse::Ok().json(schedule))
+}
@@ -0,0 +1,156 @@
+// Append-only audit log of administrative and enrollment actions. Every
+// mutation records who did it, what changed and when; entries are appended to
+// audit.jsonl one JSON object per line and never rewritten.
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use utoipa::{IntoParams, ToSchema};
+
+use super::error::ApiErrorBody;
+use super::AppState;
+
+// Fields whose values are never written to the log
+const REDACTED_FIELDS: [&str; 2] = ["password", "activation"];
+
+// One recorded action
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct AuditEntry {
+    id: u64,
+    timestamp: DateTime<Utc>,
+    actor: String,
+    action: String,
+    target: String,
+    // The fields that changed, as they were before and after the action
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    after: Option<Value>,
+}
+
+// Filters for querying the log; every given filter must match
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct AuditQuery {
+    // Username that performed the action
+    actor: Option<String>,
+    // What was acted on, e.g. "course:12" or "user:alice"
+    target: Option<String>,
+    // Only entries at or after this time (RFC 3339)
+    from: Option<DateTime<Utc>>,
+    // Only entries before this time (RFC 3339)
+    to: Option<DateTime<Utc>>,
+}
+
+// Loads the log from audit.jsonl, skipping lines that can't be parsed
+pub fn load_audit_log() -> Vec<AuditEntry> {
+    let Ok(data) = fs::read_to_string("audit.jsonl") else {
+        return Vec::new();
+    };
+    data.lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+// Appends one entry to audit.jsonl
+fn append_entry(entry: &AuditEntry) -> Result<(), std::io::Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open("audit.jsonl")?;
+    let line = serde_json::to_string(entry)?;
+    writeln!(file, "{}", line)
+}
+
+// Hides sensitive values, keeping the key so a change is still visible
+fn redact(value: &mut Value) {
+    if let Value::Object(map) = value {
+        for field in REDACTED_FIELDS {
+            if let Some(v) = map.get_mut(field) {
+                *v = Value::String("[redacted]".to_string());
+            }
+        }
+    }
+}
+
+// Reduces two snapshots to the fields that differ; non-objects are kept whole
+fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+    let (mut before, mut after) = match (before, after) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut changed_old = Map::new();
+            let mut changed_new = Map::new();
+            for key in old.keys().chain(new.keys()) {
+                let (a, b) = (old.get(key), new.get(key));
+                if a != b && !changed_old.contains_key(key) && !changed_new.contains_key(key) {
+                    changed_old.insert(key.clone(), a.cloned().unwrap_or(Value::Null));
+                    changed_new.insert(key.clone(), b.cloned().unwrap_or(Value::Null));
+                }
+            }
+            (Some(Value::Object(changed_old)), Some(Value::Object(changed_new)))
+        }
+        other => other,
+    };
+
+    if let Some(value) = before.as_mut() {
+        redact(value);
+    }
+    if let Some(value) = after.as_mut() {
+        redact(value);
+    }
+    (before, after)
+}
+
+// Records an action with snapshots of the target before and after it
+pub fn record<B: Serialize, A: Serialize>(
+    state: &AppState,
+    actor: &str,
+    action: &str,
+    target: String,
+    before: Option<&B>,
+    after: Option<&A>,
+) {
+    let before = before.and_then(|b| serde_json::to_value(b).ok());
+    let after = after.and_then(|a| serde_json::to_value(a).ok());
+    let (before, after) = diff(before, after);
+
+    let mut log = state.audit.lock().unwrap();
+    let entry = AuditEntry {
+        id: log.last().map_or(1, |e| e.id + 1),
+        timestamp: Utc::now(),
+        actor: actor.to_string(),
+        action: action.to_string(),
+        target,
+        before,
+        after,
+    };
+
+    // The action itself already succeeded, so a failed write is reported but not returned
+    if let Err(e) = append_entry(&entry) {
+        println!("Failed to write audit log: {:?}", e);
+    }
+    log.push(entry);
+}
+
+// GET /api/audit
+#[utoipa::path(
+    get,
+    path = "/api/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Matching audit entries, oldest first", body = Vec<AuditEntry>),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn query_audit_log(query: web::Query<AuditQuery>, state: web::Data<AppState>) -> impl Responder {
+    let log = state.audit.lock().unwrap();
+    let matching: Vec<&AuditEntry> = log
+        .iter()
+        .filter(|e| query.actor.as_ref().is_none_or(|actor| e.actor == *actor))
+        .filter(|e| query.target.as_ref().is_none_or(|target| e.target == *target))
+        .filter(|e| query.from.is_none_or(|from| e.timestamp >= from))
+        .filter(|e| query.to.is_none_or(|to| e.timestamp < to))
+        .collect();
+    HttpResponse::Ok().json(matching)
+}
@@ -0,0 +1,161 @@
+// Authentication and role checks. `AuthUser` resolves the logged-in account
+// from the session, and `RequireRole<R>` additionally checks its role. Routes
+// are wrapped with `require_role::<R>` in `api_routes` so handlers no longer
+// check roles themselves.
+use std::fmt;
+use std::future::{ready, Ready};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, FromRequest, HttpRequest};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::two_factor::two_factor_enabled;
+use crate::AppState;
+
+// Routes still open to a user who has to change their password first
+const PASSWORD_CHANGE_PATHS: [&str; 2] = ["/api/me", "/api/me/password"];
+
+// Routes still open to an admin who hasn't set up two-factor authentication yet
+const TWO_FACTOR_SETUP_PATHS: [&str; 4] = ["/api/me", "/api/me/password", "/api/me/two-factor", "/api/me/two-factor/confirm"];
+
+// What an account is allowed to do
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Student,
+    Instructor,
+    Advisor,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Admin => f.write_str("admin"),
+            Role::Student => f.write_str("student"),
+            Role::Instructor => f.write_str("instructor"),
+            Role::Advisor => f.write_str("advisor"),
+        }
+    }
+}
+
+// The account behind the current session
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct AuthUser {
+    pub username: String,
+    pub role: Role,
+}
+
+impl AuthUser {
+    // Looks up the logged-in user and their current role
+    fn from_request(req: &HttpRequest) -> Result<Self, ApiError> {
+        let state = req
+            .app_data::<web::Data<AppState>>()
+            .expect("AppState is registered in main");
+
+        // Read the session and the user list one at a time, login locks them in the other order
+        let username = state
+            .session
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|s| s.username.clone())
+            .ok_or(ApiError::NotAuthenticated)?;
+
+        let users = state.users.lock().unwrap();
+        let user = users
+            .iter()
+            .find(|u| u.username == username)
+            .ok_or(ApiError::NotAuthenticated)?;
+
+        if user.must_change_password && !PASSWORD_CHANGE_PATHS.contains(&req.path()) {
+            return Err(ApiError::PasswordChangeRequired);
+        }
+        let role = user.role;
+        drop(users);
+
+        if role == Role::Admin && !TWO_FACTOR_SETUP_PATHS.contains(&req.path()) && !two_factor_enabled(state, &username) {
+            return Err(ApiError::TwoFactorSetupRequired);
+        }
+
+        Ok(AuthUser { username, role })
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(AuthUser::from_request(req))
+    }
+}
+
+// Marker types naming a role at the type level, e.g. `RequireRole<Admin>`
+pub trait RoleMarker: 'static {
+    const ROLE: Role;
+}
+
+pub struct Admin;
+pub struct Student;
+pub struct Instructor;
+pub struct Advisor;
+
+impl RoleMarker for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+impl RoleMarker for Student {
+    const ROLE: Role = Role::Student;
+}
+
+impl RoleMarker for Instructor {
+    const ROLE: Role = Role::Instructor;
+}
+
+impl RoleMarker for Advisor {
+    const ROLE: Role = Role::Advisor;
+}
+
+// Extracts the logged-in user, failing with 403 unless they have role `R`
+pub struct RequireRole<R: RoleMarker> {
+    user: AuthUser,
+    _role: PhantomData<R>,
+}
+
+impl<R: RoleMarker> Deref for RequireRole<R> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.user
+    }
+}
+
+impl<R: RoleMarker> FromRequest for RequireRole<R> {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(AuthUser::from_request(req).and_then(|user| {
+            if user.role != R::ROLE {
+                return Err(ApiError::Forbidden(format!("This action requires the {} role", R::ROLE)));
+            }
+            Ok(RequireRole { user, _role: PhantomData })
+        }))
+    }
+}
+
+// Route middleware that rejects the request unless the user has role `R`
+pub async fn require_role<R: RoleMarker>(
+    _: RequireRole<R>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    next.call(req).await
+}
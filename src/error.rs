@@ -0,0 +1,266 @@
+// Error type shared by every handler. Each failure has a stable code that
+// clients can match on, plus a human-readable message and optional details.
+use std::fmt;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::import::RowError;
+use crate::ConflictDetails;
+
+// Stable machine-readable error codes
+#[derive(Serialize, ToSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    InvalidCredentials,
+    SessionActive,
+    NotAuthenticated,
+    Forbidden,
+    InvalidRequest,
+    ValidationFailed,
+    UsernameTaken,
+    ProtectedAccount,
+    CourseNotFound,
+    UserNotFound,
+    NotInSchedule,
+    AlreadyInSchedule,
+    OverrideNotFound,
+    OverrideAlreadyDecided,
+    VersionNotFound,
+    NotificationNotFound,
+    RoomNotFound,
+    ShareLinkNotFound,
+    ImportFailed,
+    InvalidToken,
+    PasswordChangeRequired,
+    TwoFactorSetupRequired,
+    InvalidTwoFactorCode,
+    LoginThrottled,
+    AccountLocked,
+    TimeConflict,
+    InvalidScheduleState,
+    StorageFailure,
+}
+
+// JSON body of every error response
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct ApiErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+// Every way a request can fail
+#[derive(Debug)]
+pub enum ApiError {
+    InvalidCredentials,
+    SessionActive,
+    NotAuthenticated,
+    Forbidden(String),
+    InvalidRequest(String),
+    Validation { field: &'static str, message: String },
+    UsernameTaken,
+    ProtectedAccount(String),
+    CourseNotFound,
+    UserNotFound,
+    NotInSchedule,
+    AlreadyInSchedule,
+    OverrideNotFound,
+    OverrideDecided,
+    VersionNotFound,
+    NotificationNotFound,
+    RoomNotFound,
+    ShareLinkNotFound,
+    // Rows of an import that failed validation
+    ImportFailed(Vec<RowError>),
+    // An activation or reset token that is unknown, used or expired
+    InvalidToken,
+    PasswordChangeRequired,
+    // An admin account that hasn't enrolled an authenticator app yet
+    TwoFactorSetupRequired,
+    // A one-time or recovery code that is wrong or was already used
+    InvalidTwoFactorCode,
+    // Too many failed logins recently; seconds until the next try is allowed
+    LoginThrottled(u64),
+    AccountLocked(u64),
+    TimeConflict(ConflictDetails),
+    ScheduleState(String),
+    Storage(String),
+}
+
+impl ApiError {
+    // Wraps a failed write to one of the JSON data files
+    pub fn storage(what: &str, e: std::io::Error) -> Self {
+        ApiError::Storage(format!("Failed to save {}: {}", what, e))
+    }
+
+    // Shorthand for a validation failure on one input field
+    pub fn validation(field: &'static str, message: &str) -> Self {
+        ApiError::Validation { field, message: message.to_string() }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ApiError::InvalidCredentials => ErrorCode::InvalidCredentials,
+            ApiError::SessionActive => ErrorCode::SessionActive,
+            ApiError::NotAuthenticated => ErrorCode::NotAuthenticated,
+            ApiError::Forbidden(_) => ErrorCode::Forbidden,
+            ApiError::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            ApiError::Validation { .. } => ErrorCode::ValidationFailed,
+            ApiError::UsernameTaken => ErrorCode::UsernameTaken,
+            ApiError::ProtectedAccount(_) => ErrorCode::ProtectedAccount,
+            ApiError::CourseNotFound => ErrorCode::CourseNotFound,
+            ApiError::UserNotFound => ErrorCode::UserNotFound,
+            ApiError::NotInSchedule => ErrorCode::NotInSchedule,
+            ApiError::AlreadyInSchedule => ErrorCode::AlreadyInSchedule,
+            ApiError::OverrideNotFound => ErrorCode::OverrideNotFound,
+            ApiError::OverrideDecided => ErrorCode::OverrideAlreadyDecided,
+            ApiError::VersionNotFound => ErrorCode::VersionNotFound,
+            ApiError::NotificationNotFound => ErrorCode::NotificationNotFound,
+            ApiError::RoomNotFound => ErrorCode::RoomNotFound,
+            ApiError::ShareLinkNotFound => ErrorCode::ShareLinkNotFound,
+            ApiError::ImportFailed(_) => ErrorCode::ImportFailed,
+            ApiError::InvalidToken => ErrorCode::InvalidToken,
+            ApiError::PasswordChangeRequired => ErrorCode::PasswordChangeRequired,
+            ApiError::TwoFactorSetupRequired => ErrorCode::TwoFactorSetupRequired,
+            ApiError::InvalidTwoFactorCode => ErrorCode::InvalidTwoFactorCode,
+            ApiError::LoginThrottled(_) => ErrorCode::LoginThrottled,
+            ApiError::AccountLocked(_) => ErrorCode::AccountLocked,
+            ApiError::TimeConflict(_) => ErrorCode::TimeConflict,
+            ApiError::ScheduleState(_) => ErrorCode::InvalidScheduleState,
+            ApiError::Storage(_) => ErrorCode::StorageFailure,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::InvalidCredentials => "Invalid credentials".to_string(),
+            ApiError::SessionActive => "Another user is already logged in".to_string(),
+            ApiError::NotAuthenticated => "Not logged in".to_string(),
+            ApiError::CourseNotFound => "Course not found".to_string(),
+            ApiError::UserNotFound => "User not found".to_string(),
+            ApiError::UsernameTaken => "Username already exists".to_string(),
+            ApiError::NotInSchedule => "Course not in schedule".to_string(),
+            ApiError::AlreadyInSchedule => "Course already in schedule".to_string(),
+            ApiError::OverrideNotFound => "Override request not found".to_string(),
+            ApiError::OverrideDecided => "Override request was already decided".to_string(),
+            ApiError::VersionNotFound => "Course version not found".to_string(),
+            ApiError::NotificationNotFound => "Notification not found".to_string(),
+            ApiError::RoomNotFound => "Planning room not found".to_string(),
+            ApiError::ShareLinkNotFound => "Share link not found".to_string(),
+            ApiError::ImportFailed(errors) => format!("{} rows failed validation, nothing was imported", errors.len()),
+            ApiError::InvalidToken => "The token is invalid or has expired".to_string(),
+            ApiError::PasswordChangeRequired => "Change your password before continuing".to_string(),
+            ApiError::TwoFactorSetupRequired => "Set up two-factor authentication before continuing".to_string(),
+            ApiError::InvalidTwoFactorCode => "Invalid authentication code".to_string(),
+            ApiError::LoginThrottled(seconds) => format!("Too many failed logins, try again in {} seconds", seconds),
+            ApiError::AccountLocked(seconds) => {
+                format!("Account locked after too many failed logins, try again in {} minutes", seconds.div_ceil(60))
+            }
+            ApiError::TimeConflict(_) => "Time conflict with existing course".to_string(),
+            ApiError::Forbidden(message)
+            | ApiError::InvalidRequest(message)
+            | ApiError::Validation { message, .. }
+            | ApiError::ProtectedAccount(message)
+            | ApiError::ScheduleState(message)
+            | ApiError::Storage(message) => message.clone(),
+        }
+    }
+
+    pub fn body(&self) -> ApiErrorBody {
+        ApiErrorBody {
+            code: self.code(),
+            message: self.message(),
+            details: match self {
+                ApiError::TimeConflict(details) => serde_json::to_value(details).ok(),
+                ApiError::ImportFailed(errors) => serde_json::to_value(errors).ok(),
+                ApiError::LoginThrottled(seconds) | ApiError::AccountLocked(seconds) => {
+                    Some(serde_json::json!({ "retry_after": seconds }))
+                }
+                _ => None,
+            },
+            field: match self {
+                ApiError::Validation { field, .. } => Some(field.to_string()),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message())
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidCredentials | ApiError::NotAuthenticated | ApiError::InvalidTwoFactorCode => StatusCode::UNAUTHORIZED,
+            ApiError::SessionActive
+            | ApiError::Forbidden(_)
+            | ApiError::PasswordChangeRequired
+            | ApiError::TwoFactorSetupRequired => StatusCode::FORBIDDEN,
+            ApiError::InvalidRequest(_)
+            | ApiError::Validation { .. }
+            | ApiError::UsernameTaken
+            | ApiError::ProtectedAccount(_)
+            | ApiError::AlreadyInSchedule
+            | ApiError::ImportFailed(_)
+            | ApiError::InvalidToken => StatusCode::BAD_REQUEST,
+            ApiError::CourseNotFound
+            | ApiError::UserNotFound
+            | ApiError::NotInSchedule
+            | ApiError::OverrideNotFound
+            | ApiError::VersionNotFound
+            | ApiError::NotificationNotFound
+            | ApiError::RoomNotFound
+            | ApiError::ShareLinkNotFound => StatusCode::NOT_FOUND,
+            ApiError::TimeConflict(_) | ApiError::ScheduleState(_) | ApiError::OverrideDecided => StatusCode::CONFLICT,
+            ApiError::LoginThrottled(_) | ApiError::AccountLocked(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::LoginThrottled(seconds) | ApiError::AccountLocked(seconds) = self {
+            response.insert_header(("Retry-After", seconds.to_string()));
+        }
+        response.json(self.body())
+    }
+}
+
+// Error for the deprecated RPC-style routes, which report missing records as 400
+#[derive(Debug)]
+pub struct LegacyError(pub ApiError);
+
+impl From<ApiError> for LegacyError {
+    fn from(e: ApiError) -> Self {
+        LegacyError(e)
+    }
+}
+
+impl fmt::Display for LegacyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl ResponseError for LegacyError {
+    fn status_code(&self) -> StatusCode {
+        match self.0.status_code() {
+            StatusCode::NOT_FOUND => StatusCode::BAD_REQUEST,
+            status => status,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.0.body())
+    }
+}
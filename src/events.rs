@@ -0,0 +1,130 @@
+// Live updates over Server-Sent Events. Mutations publish to a broadcast
+// channel; every open /api/events stream gets catalog changes and enrollment
+// counts, plus schedule changes and notifications for the logged-in user.
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{BroadcastStream, IntervalStream};
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use super::auth::AuthUser;
+use super::notifications::Notification;
+use super::{AppState, Course, Schedule};
+
+// Events buffered per stream before a slow client starts missing them
+const CHANNEL_CAPACITY: usize = 256;
+
+// How often an idle stream sends a comment so proxies keep it open
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// One change pushed to clients
+#[derive(Serialize, Clone, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    CourseAdded { course: Course },
+    CourseUpdated { course: Course },
+    CourseDeleted { course_id: u32 },
+    // Number of students with the course in their schedule
+    Enrollment { course_id: u32, enrolled: usize },
+    // Only sent to the schedule's owner
+    ScheduleChanged { schedule: Schedule },
+    // Only sent to the recipient
+    Notification { notification: Notification },
+}
+
+// An event and who may see it; `None` means everyone
+#[derive(Clone)]
+pub struct Published {
+    audience: Option<String>,
+    event: Event,
+}
+
+impl Event {
+    // SSE event name, matching the `type` field
+    fn name(&self) -> &'static str {
+        match self {
+            Event::CourseAdded { .. } => "course_added",
+            Event::CourseUpdated { .. } => "course_updated",
+            Event::CourseDeleted { .. } => "course_deleted",
+            Event::Enrollment { .. } => "enrollment",
+            Event::ScheduleChanged { .. } => "schedule_changed",
+            Event::Notification { .. } => "notification",
+        }
+    }
+}
+
+// Creates the channel mutations publish to
+pub fn event_channel() -> broadcast::Sender<Published> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+// Sends an event to every open stream
+pub fn publish(state: &AppState, event: Event) {
+    // Sending only fails when nobody is listening, which is fine
+    let _ = state.events.send(Published { audience: None, event });
+}
+
+// Sends an event only to streams opened by the given user
+pub fn publish_to(state: &AppState, username: &str, event: Event) {
+    let _ = state.events.send(Published { audience: Some(username.to_string()), event });
+}
+
+// Tells a student their schedule changed
+pub fn schedule_changed(state: &AppState, schedules: &HashMap<String, Schedule>, username: &str) {
+    let schedule = schedules.get(username).cloned().unwrap_or_default();
+    publish_to(state, username, Event::ScheduleChanged { schedule });
+}
+
+// Announces how many students now have a course in their schedule
+pub fn enrollment_changed(state: &AppState, schedules: &HashMap<String, Schedule>, course_id: u32) {
+    let enrolled = schedules
+        .values()
+        .filter(|schedule| schedule.courses.iter().any(|c| c.course_id == course_id))
+        .count();
+    publish(state, Event::Enrollment { course_id, enrolled });
+}
+
+// Formats one event in SSE wire format
+fn sse_frame(event: &Event) -> Bytes {
+    let data = serde_json::to_string(event).unwrap_or_default();
+    Bytes::from(format!("event: {}\ndata: {}\n\n", event.name(), data))
+}
+
+// GET /api/events
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "events",
+    responses(
+        (status = 200, description = "Stream of catalog changes, plus schedule changes and notifications when logged in. \
+            A `resync` event means some events were missed and the client should reload.", content_type = "text/event-stream", body = Event),
+    )
+)]
+pub async fn event_stream(user: Option<AuthUser>, state: web::Data<AppState>) -> HttpResponse {
+    let username = user.map(|u| u.username);
+    let session_state = state.clone();
+    // Personal events stop once the user who opened the stream logs out
+    let may_see = move |audience: &Option<String>| match audience {
+        None => true,
+        Some(recipient) => {
+            username.as_ref() == Some(recipient)
+                && session_state.session.lock().unwrap().as_ref().is_some_and(|s| s.username == *recipient)
+        }
+    };
+    let events = BroadcastStream::new(state.events.subscribe()).filter_map(move |received| match received {
+        Ok(Published { audience, event }) => may_see(&audience).then(|| Ok::<_, actix_web::Error>(sse_frame(&event))),
+        // The client fell behind and missed events, so have it reload
+        Err(_) => Some(Ok(Bytes::from_static(b"event: resync\ndata: {}\n\n"))),
+    });
+    let keep_alive = IntervalStream::new(tokio::time::interval(KEEP_ALIVE)).map(|_| Ok(Bytes::from_static(b": keep-alive\n\n")));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events.merge(keep_alive))
+}
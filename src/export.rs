@@ -0,0 +1,399 @@
+// Catalog and enrollment exports as CSV, JSON or XLSX. The rows are written on
+// a separate thread into a bounded channel that feeds the response body, so a
+// large export is sent as it is produced instead of being built in memory.
+use std::io::{self, Write};
+
+use actix_web::web::{self, Bytes};
+use actix_web::HttpResponse;
+use quick_xml::escape::escape;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use utoipa::{IntoParams, ToSchema};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::error::ApiErrorBody;
+use super::{format_minutes, parse_meeting_time, AppState, Course, ScheduleStatus};
+
+// Bytes collected before a chunk is handed to the response
+const CHUNK_BYTES: usize = 64 * 1024;
+
+// Chunks waiting to be sent before the writer thread blocks
+const CHANNEL_CHUNKS: usize = 8;
+
+// Content type of XLSX files
+const XLSX_CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet";
+
+// File formats an export can be written in
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+    Xlsx,
+}
+
+// Format and filters for an export. Filters match part of the field, ignoring case,
+// the same way the search boxes on the course pages do.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub struct ExportOptions {
+    #[serde(default)]
+    format: ExportFormat,
+    dept_code: Option<String>,
+    course_number: Option<String>,
+    instructor: Option<String>,
+    description: Option<String>,
+}
+
+// One weekly class meeting
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ExportedMeeting {
+    day: String,
+    start: String,
+    end: String,
+}
+
+// A catalog course with its meeting time broken into separate meetings
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ExportedCourse {
+    id: u32,
+    dept_code: String,
+    course_number: String,
+    instructor: String,
+    description: String,
+    location: String,
+    meeting_time: String,
+    meetings: Vec<ExportedMeeting>,
+}
+
+// Who has a course in their schedule
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CourseEnrollment {
+    course_id: u32,
+    dept_code: String,
+    course_number: String,
+    instructor: String,
+    meeting_time: String,
+    // Students with the course in their schedule
+    enrolled: usize,
+    // Of those, students whose schedule was approved
+    registered: usize,
+    students: Vec<String>,
+}
+
+// Something that can be written as one row of an export
+trait ExportRow: Serialize + Send + 'static {
+    // Column headers for CSV and XLSX
+    const HEADERS: &'static [&'static str];
+
+    // Values for CSV and XLSX, in header order
+    fn cells(&self) -> Vec<String>;
+}
+
+impl ExportRow for ExportedCourse {
+    // Same headers the import reads by default, so an export can be imported again
+    const HEADERS: &'static [&'static str] = &["id", "dept_code", "course_number", "instructor", "description", "location", "meeting_time"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.dept_code.clone(),
+            self.course_number.clone(),
+            self.instructor.clone(),
+            self.description.clone(),
+            self.location.clone(),
+            self.meeting_time.clone(),
+        ]
+    }
+}
+
+impl ExportRow for CourseEnrollment {
+    const HEADERS: &'static [&'static str] =
+        &["course_id", "dept_code", "course_number", "instructor", "meeting_time", "enrolled", "registered", "students"];
+
+    fn cells(&self) -> Vec<String> {
+        vec![
+            self.course_id.to_string(),
+            self.dept_code.clone(),
+            self.course_number.clone(),
+            self.instructor.clone(),
+            self.meeting_time.clone(),
+            self.enrolled.to_string(),
+            self.registered.to_string(),
+            self.students.join("; "),
+        ]
+    }
+}
+
+impl ExportOptions {
+    // Whether a course passes every filter that was given
+    fn matches(&self, course: &Course) -> bool {
+        let contains = |value: &str, filter: &Option<String>| {
+            filter.as_ref().is_none_or(|f| value.to_lowercase().contains(&f.trim().to_lowercase()))
+        };
+        contains(&course.dept_code, &self.dept_code)
+            && contains(&course.course_number, &self.course_number)
+            && contains(&course.instructor, &self.instructor)
+            && contains(&course.description, &self.description)
+    }
+}
+
+// Writer that sends what is written to the response in chunks
+struct ChunkWriter {
+    sender: mpsc::Sender<Result<Bytes, io::Error>>,
+    buffer: Vec<u8>,
+}
+
+impl ChunkWriter {
+    // Passes on a failure so the client sees a broken download rather than a short one
+    fn fail(&self, e: impl std::fmt::Display) {
+        let _ = self.sender.blocking_send(Err(io::Error::other(e.to_string())));
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() >= CHUNK_BYTES {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = Bytes::from(std::mem::take(&mut self.buffer));
+        // The receiver is gone when the client disconnected, which stops the export
+        self.sender
+            .blocking_send(Ok(chunk))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))
+    }
+}
+
+// Writes rows as CSV with a header line
+fn write_csv<T: ExportRow>(out: &mut ChunkWriter, rows: &[T]) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(T::HEADERS)?;
+    for row in rows {
+        writer.write_record(row.cells())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes rows as a JSON array, one row at a time
+fn write_json<T: ExportRow>(out: &mut ChunkWriter, rows: &[T]) -> io::Result<()> {
+    out.write_all(b"[")?;
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, row)?;
+    }
+    out.write_all(b"]")?;
+    out.flush()
+}
+
+// Column letters of a zero-based column index, 0 is "A"
+fn column_name(mut index: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.insert(0, b'A' + (index % 26) as u8);
+        if index < 26 {
+            return String::from_utf8(name).unwrap_or_default();
+        }
+        index = index / 26 - 1;
+    }
+}
+
+// One worksheet row of inline string cells
+fn sheet_row(number: usize, cells: &[String]) -> String {
+    let mut xml = format!("<row r=\"{}\">", number);
+    for (i, value) in cells.iter().enumerate() {
+        xml.push_str(&format!(
+            "<c r=\"{}{}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+            column_name(i),
+            number,
+            escape(value.as_str())
+        ));
+    }
+    xml.push_str("</row>");
+    xml
+}
+
+// Writes rows as a single-sheet XLSX workbook. The zip is written front to back
+// so it can be streamed; the sheet uses inline strings to avoid a shared string table.
+fn write_xlsx<T: ExportRow>(out: &mut ChunkWriter, sheet_name: &str, rows: &[T]) -> zip::result::ZipResult<()> {
+    let mut zip = ZipWriter::new_stream(out);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    zip.start_file("[Content_Types].xml", options)?;
+    zip.write_all(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">"#,
+        r#"<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>"#,
+        r#"<Default Extension="xml" ContentType="application/xml"/>"#,
+        r#"<Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/>"#,
+        r#"<Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+        r#"</Types>"#,
+    ).as_bytes())?;
+
+    zip.start_file("_rels/.rels", options)?;
+    zip.write_all(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+        r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/>"#,
+        r#"</Relationships>"#,
+    ).as_bytes())?;
+
+    zip.start_file("xl/workbook.xml", options)?;
+    zip.write_all(format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">"#,
+            r#"<sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+        ),
+        escape(sheet_name)
+    ).as_bytes())?;
+
+    zip.start_file("xl/_rels/workbook.xml.rels", options)?;
+    zip.write_all(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+        r#"<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/>"#,
+        r#"</Relationships>"#,
+    ).as_bytes())?;
+
+    zip.start_file("xl/worksheets/sheet1.xml", options)?;
+    zip.write_all(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#,
+        r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    ).as_bytes())?;
+    let headers: Vec<String> = T::HEADERS.iter().map(|h| h.to_string()).collect();
+    zip.write_all(sheet_row(1, &headers).as_bytes())?;
+    for (i, row) in rows.iter().enumerate() {
+        zip.write_all(sheet_row(i + 2, &row.cells()).as_bytes())?;
+    }
+    zip.write_all(b"</sheetData></worksheet>")?;
+
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+// Starts writing the rows on another thread and returns the response that streams them
+fn stream_export<T: ExportRow>(format: ExportFormat, name: &'static str, rows: Vec<T>) -> HttpResponse {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CHUNKS);
+    std::thread::spawn(move || {
+        let mut out = ChunkWriter { sender, buffer: Vec::new() };
+        let result = match format {
+            ExportFormat::Csv => write_csv(&mut out, &rows).map_err(|e| e.to_string()),
+            ExportFormat::Json => write_json(&mut out, &rows).map_err(|e| e.to_string()),
+            ExportFormat::Xlsx => write_xlsx(&mut out, name, &rows).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = result {
+            println!("Failed to write {} export: {}", name, e);
+            out.fail(e);
+        }
+    });
+
+    let (extension, content_type) = match format {
+        ExportFormat::Csv => ("csv", "text/csv; charset=utf-8"),
+        ExportFormat::Json => ("json", "application/json"),
+        ExportFormat::Xlsx => ("xlsx", XLSX_CONTENT_TYPE),
+    };
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.{}\"", name, extension)))
+        .streaming(ReceiverStream::new(receiver))
+}
+
+// GET /api/courses/export
+#[utoipa::path(
+    get,
+    path = "/api/courses/export",
+    tag = "courses",
+    params(ExportOptions),
+    responses(
+        (status = 200, description = "The matching courses as a file download", content(
+            (String = "text/csv"),
+            (Vec<ExportedCourse> = "application/json"),
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        )),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+    )
+)]
+pub async fn export_courses(options: web::Query<ExportOptions>, state: web::Data<AppState>) -> HttpResponse {
+    let mut rows: Vec<ExportedCourse> = {
+        let courses = state.courses.lock().unwrap();
+        courses
+            .iter()
+            .filter(|course| options.matches(course))
+            .map(|course| ExportedCourse {
+                id: course.id,
+                dept_code: course.dept_code.clone(),
+                course_number: course.course_number.clone(),
+                instructor: course.instructor.clone(),
+                description: course.description.clone(),
+                location: course.location.clone(),
+                meeting_time: course.meeting_time.clone(),
+                meetings: parse_meeting_time(&course.meeting_time)
+                    .into_iter()
+                    .map(|slot| ExportedMeeting { day: slot.day, start: format_minutes(slot.start), end: format_minutes(slot.end) })
+                    .collect(),
+            })
+            .collect()
+    };
+    rows.sort_by_key(|course| course.id);
+    stream_export(options.format, "courses", rows)
+}
+
+// GET /api/courses/enrollment/export
+#[utoipa::path(
+    get,
+    path = "/api/courses/enrollment/export",
+    tag = "courses",
+    params(ExportOptions),
+    responses(
+        (status = 200, description = "Enrollment for each matching course as a file download", content(
+            (String = "text/csv"),
+            (Vec<CourseEnrollment> = "application/json"),
+            (Vec<u8> = "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+        )),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn export_enrollment(options: web::Query<ExportOptions>, state: web::Data<AppState>) -> HttpResponse {
+    let mut rows: Vec<CourseEnrollment> = {
+        let courses = state.courses.lock().unwrap();
+        let schedules = state.schedules.lock().unwrap();
+        courses
+            .iter()
+            .filter(|course| options.matches(course))
+            .map(|course| {
+                let mut enrolled: Vec<(&String, bool)> = schedules
+                    .iter()
+                    .filter(|(_, schedule)| schedule.courses.iter().any(|c| c.course_id == course.id))
+                    .map(|(username, schedule)| (username, schedule.status == ScheduleStatus::Approved))
+                    .collect();
+                enrolled.sort();
+                CourseEnrollment {
+                    course_id: course.id,
+                    dept_code: course.dept_code.clone(),
+                    course_number: course.course_number.clone(),
+                    instructor: course.instructor.clone(),
+                    meeting_time: course.meeting_time.clone(),
+                    enrolled: enrolled.len(),
+                    registered: enrolled.iter().filter(|(_, approved)| *approved).count(),
+                    students: enrolled.into_iter().map(|(username, _)| username.clone()).collect(),
+                }
+            })
+            .collect()
+    };
+    rows.sort_by_key(|row| row.course_id);
+    stream_export(options.format, "enrollment", rows)
+}
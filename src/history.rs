@@ -0,0 +1,496 @@
+// Version history for catalog courses. Every create, update, delete and
+// restore adds a version holding the full course, along with any schedule
+// entries the change removed, so admins can compare versions, see who a change
+// dropped, and roll a course back or undelete it. Restoring brings back the
+// course and puts its removed entries back in students' schedules, except where
+// the restored meeting time would now clash.
+use std::collections::HashMap;
+use std::fs;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::events::{enrollment_changed, publish, schedule_changed, Event};
+use super::notifications::{course_label, notify, NotificationKind};
+use super::{
+    course_impact, persist_catalog, schedule_conflicts, unschedule_students, AppState, Course, Schedule, ScheduledCourse, StudentConflicts,
+};
+
+// What produced a version
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionChange {
+    // The course as it was before history was kept
+    Original,
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+}
+
+// A schedule entry removed from a student's schedule by a change
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct RemovedEntry {
+    username: String,
+    entry: ScheduledCourse,
+}
+
+// One saved state of a course
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct CourseVersion {
+    version: u32,
+    timestamp: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    change: VersionChange,
+    course: Course,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    removed_entries: Vec<RemovedEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    restored_from: Option<u32>,
+}
+
+// One field that differs between two versions
+#[derive(Serialize, Debug, ToSchema)]
+pub struct FieldChange {
+    field: String,
+    from: Value,
+    to: Value,
+}
+
+// Which version to compare against
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct DiffQuery {
+    // Version to compare with, defaults to the one before. The first version is compared with an empty course.
+    against: Option<u32>,
+}
+
+// Result of restoring a version
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RestoreOutcome {
+    course: Course,
+    // Students who still have the course in their schedule
+    kept_students: Vec<String>,
+    // Students dropped from the course because the restored meeting time clashes with their schedule
+    dropped_students: Vec<StudentConflicts>,
+    // Students whose entry removed by a later version was put back
+    restored_students: Vec<String>,
+    // Students whose removed entry was not put back because it would now clash
+    unrestored_students: Vec<StudentConflicts>,
+}
+
+// Loads every course's history from file
+pub fn load_course_history() -> HashMap<u32, Vec<CourseVersion>> {
+    fs::read_to_string("course_history.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves every course's history
+fn save_course_history(history: &HashMap<u32, Vec<CourseVersion>>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(history)?;
+    fs::write("course_history.json", data)?;
+    Ok(())
+}
+
+// Highest course ID that has ever existed, so deleted IDs are never handed out again
+pub fn highest_course_id(state: &AppState) -> u32 {
+    state.course_history.lock().unwrap().keys().copied().max().unwrap_or(0)
+}
+
+// Adds a version for a course change. `before` seeds the history of courses created before it was kept.
+pub fn record_version(
+    state: &AppState,
+    author: &str,
+    change: VersionChange,
+    before: Option<&Course>,
+    course: &Course,
+    removed_entries: Vec<(String, ScheduledCourse)>,
+) -> Result<(), ApiError> {
+    let mut history = state.course_history.lock().unwrap();
+    let versions = history.entry(course.id).or_default();
+    if let (true, Some(before)) = (versions.is_empty(), before) {
+        versions.push(CourseVersion {
+            version: 1,
+            timestamp: Utc::now(),
+            author: None,
+            change: VersionChange::Original,
+            course: before.clone(),
+            removed_entries: Vec::new(),
+            restored_from: None,
+        });
+    }
+
+    versions.push(CourseVersion {
+        version: versions.len() as u32 + 1,
+        timestamp: Utc::now(),
+        author: Some(author.to_string()),
+        change,
+        course: course.clone(),
+        removed_entries: removed_entries
+            .into_iter()
+            .map(|(username, entry)| RemovedEntry { username, entry })
+            .collect(),
+        restored_from: None,
+    });
+    save_course_history(&history).map_err(|e| ApiError::storage("course history", e))
+}
+
+// Lists the fields that differ between two courses; without `from` every field is new
+fn course_diff(from: Option<&Course>, to: &Course) -> Vec<FieldChange> {
+    let Ok(Value::Object(to)) = serde_json::to_value(to) else {
+        return Vec::new();
+    };
+    let from = match from.map(serde_json::to_value) {
+        Some(Ok(Value::Object(from))) => from,
+        _ => serde_json::Map::new(),
+    };
+    to.into_iter()
+        .filter_map(|(field, new)| {
+            let old = from.get(&field).cloned().unwrap_or(Value::Null);
+            (old != new).then_some(FieldChange { field, from: old, to: new })
+        })
+        .collect()
+}
+
+// Looks up one version of a course
+fn find_version(state: &AppState, id: u32, version: u32) -> Result<CourseVersion, ApiError> {
+    let history = state.course_history.lock().unwrap();
+    let versions = history.get(&id).ok_or(ApiError::CourseNotFound)?;
+    versions.iter().find(|v| v.version == version).cloned().ok_or(ApiError::VersionNotFound)
+}
+
+// Puts schedule entries removed by versions after `version` back, skipping
+// students who have the course again, are in `skip`, or would now clash. Returns
+// who got the course back and who clashes.
+fn reschedule_removed_entries(
+    versions: &[CourseVersion],
+    version: u32,
+    course: &Course,
+    courses: &[Course],
+    schedules: &mut HashMap<String, Schedule>,
+    skip: &[String],
+) -> (Vec<String>, Vec<StudentConflicts>) {
+    let mut restored = Vec::new();
+    let mut clashing: Vec<StudentConflicts> = Vec::new();
+    let removed = versions.iter().filter(|v| v.version > version).flat_map(|v| &v.removed_entries);
+    for RemovedEntry { username, entry } in removed {
+        if skip.contains(username) || clashing.iter().any(|s| s.username == *username) {
+            continue;
+        }
+        let Some(schedule) = schedules.get_mut(username) else {
+            continue;
+        };
+        if schedule.courses.iter().any(|c| c.course_id == course.id) {
+            continue;
+        }
+        let conflicts = schedule_conflicts(course, schedule, courses);
+        if !conflicts.is_empty() {
+            clashing.push(StudentConflicts { username: username.clone(), conflicts });
+            continue;
+        }
+        schedule.courses.push(entry.clone());
+        schedule.mark_changed();
+        restored.push(username.clone());
+    }
+    restored.sort();
+    clashing.sort_by(|a, b| a.username.cmp(&b.username));
+    (restored, clashing)
+}
+
+// Puts a course back as it was at `version`. Enrolled students whose schedule
+// would clash with the restored course are dropped from it, and schedule entries
+// removed by later versions are put back where they still fit.
+fn restore_version(state: &AppState, actor: &str, id: u32, version: u32) -> Result<RestoreOutcome, ApiError> {
+    let mut courses = state.courses.lock().unwrap();
+    let mut schedules = state.schedules.lock().unwrap();
+    let mut history = state.course_history.lock().unwrap();
+
+    let versions = history.get_mut(&id).ok_or(ApiError::CourseNotFound)?;
+    let target = versions.iter().find(|v| v.version == version).cloned().ok_or(ApiError::VersionNotFound)?;
+    if target.change == VersionChange::Deleted {
+        return Err(ApiError::InvalidRequest("A deleted version cannot be restored, pick the one before it".to_string()));
+    }
+
+    let impact = course_impact(&courses, &schedules, id, Some(&target.course));
+    let dropped: Vec<String> = impact.conflicting_students.iter().map(|s| s.username.clone()).collect();
+    let kept: Vec<String> = impact.affected_students.into_iter().filter(|s| !dropped.contains(s)).collect();
+
+    let before = courses.iter().find(|c| c.id == id).cloned();
+    match courses.iter().position(|c| c.id == id) {
+        Some(index) => courses[index] = target.course.clone(),
+        None => {
+            let index = courses.partition_point(|c| c.id < id);
+            courses.insert(index, target.course.clone());
+        }
+    }
+    let removed = unschedule_students(&mut schedules, id, &dropped);
+    let (restored, unrestored) = reschedule_removed_entries(versions, version, &target.course, &courses, &mut schedules, &dropped);
+
+    versions.push(CourseVersion {
+        version: versions.len() as u32 + 1,
+        timestamp: Utc::now(),
+        author: Some(actor.to_string()),
+        change: VersionChange::Restored,
+        course: target.course.clone(),
+        removed_entries: removed.into_iter().map(|(username, entry)| RemovedEntry { username, entry }).collect(),
+        restored_from: Some(version),
+    });
+
+    persist_catalog(&courses, &schedules)?;
+    save_course_history(&history).map_err(|e| ApiError::storage("course history", e))?;
+    audit::record(state, actor, "course.restore", format!("course:{}", id), before.as_ref(), Some(&target.course));
+    let course = target.course.clone();
+    publish(state, if before.is_some() { Event::CourseUpdated { course } } else { Event::CourseAdded { course } });
+    for username in dropped.iter().chain(&restored) {
+        schedule_changed(state, &schedules, username);
+    }
+    enrollment_changed(state, &schedules, id);
+
+    let label = course_label(&target.course);
+    notify(
+        state,
+        &dropped,
+        NotificationKind::CourseDropped,
+        Some(id),
+        &format!("{} was restored to an earlier version that clashes with your schedule, so it was removed", label),
+    );
+    notify(state, &kept, NotificationKind::CourseRestored, Some(id), &format!("{} in your schedule was restored to an earlier version", label));
+    notify(state, &restored, NotificationKind::CourseRestored, Some(id), &format!("{} was restored and put back in your schedule", label));
+
+    Ok(RestoreOutcome {
+        course: target.course,
+        kept_students: kept,
+        dropped_students: impact.conflicting_students,
+        restored_students: restored,
+        unrestored_students: unrestored,
+    })
+}
+
+// GET /api/courses/{id}/versions
+#[utoipa::path(
+    get,
+    path = "/api/courses/{id}/versions",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "Every version of the course, oldest first", body = Vec<CourseVersion>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course has no history", body = ApiErrorBody),
+    )
+)]
+pub async fn list_versions(id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let history = state.course_history.lock().unwrap();
+    let versions = history.get(&id).ok_or(ApiError::CourseNotFound)?;
+    Ok(HttpResponse::Ok().json(versions))
+}
+
+// GET /api/courses/{id}/versions/{version}
+#[utoipa::path(
+    get,
+    path = "/api/courses/{id}/versions/{version}",
+    tag = "courses",
+    params(
+        ("id" = u32, Path, description = "Course ID"),
+        ("version" = u32, Path, description = "Version number"),
+    ),
+    responses(
+        (status = 200, description = "The version", body = CourseVersion),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course or version not found", body = ApiErrorBody),
+    )
+)]
+pub async fn get_version(path: web::Path<(u32, u32)>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (id, version) = path.into_inner();
+    Ok(HttpResponse::Ok().json(find_version(&state, id, version)?))
+}
+
+// GET /api/courses/{id}/versions/{version}/diff
+#[utoipa::path(
+    get,
+    path = "/api/courses/{id}/versions/{version}/diff",
+    tag = "courses",
+    params(
+        ("id" = u32, Path, description = "Course ID"),
+        ("version" = u32, Path, description = "Version number"),
+        DiffQuery,
+    ),
+    responses(
+        (status = 200, description = "Fields changed between the two versions", body = Vec<FieldChange>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course or version not found", body = ApiErrorBody),
+    )
+)]
+pub async fn diff_version(path: web::Path<(u32, u32)>, query: web::Query<DiffQuery>, state: web::Data<AppState>) -> Result<impl Responder, ApiError> {
+    let (id, version) = path.into_inner();
+    let to = find_version(&state, id, version)?;
+    let from = match query.against {
+        Some(against) => Some(find_version(&state, id, against)?),
+        None if version > 1 => Some(find_version(&state, id, version - 1)?),
+        None => None,
+    };
+    Ok(HttpResponse::Ok().json(course_diff(from.as_ref().map(|v| &v.course), &to.course)))
+}
+
+// POST /api/courses/{id}/versions/{version}/restore
+#[utoipa::path(
+    post,
+    path = "/api/courses/{id}/versions/{version}/restore",
+    tag = "courses",
+    params(
+        ("id" = u32, Path, description = "Course ID"),
+        ("version" = u32, Path, description = "Version number"),
+    ),
+    responses(
+        (status = 200, description = "Course restored", body = RestoreOutcome),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course or version not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn restore_course_version(admin: AuthUser, path: web::Path<(u32, u32)>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (id, version) = path.into_inner();
+    let outcome = restore_version(&state, &admin.username, id, version)?;
+    Ok(HttpResponse::Ok().json(outcome))
+}
+
+// POST /api/courses/{id}/undelete
+#[utoipa::path(
+    post,
+    path = "/api/courses/{id}/undelete",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "Course restored as it was before deletion", body = RestoreOutcome),
+        (status = 400, description = "Course is not deleted", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course has no history", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn undelete_course(admin: AuthUser, id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let version = {
+        let history = state.course_history.lock().unwrap();
+        let versions = history.get(&id).ok_or(ApiError::CourseNotFound)?;
+        match versions.last() {
+            Some(last) if last.change == VersionChange::Deleted => last.version - 1,
+            _ => return Err(ApiError::InvalidRequest("Course is not deleted".to_string())),
+        }
+    };
+
+    let outcome = restore_version(&state, &admin.username, id, version)?;
+    Ok(HttpResponse::Ok().json(outcome))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn course(id: u32, meeting_time: &str) -> Course {
+        Course {
+            id,
+            dept_code: "CS".to_string(),
+            course_number: id.to_string(),
+            instructor: "Staff".to_string(),
+            description: String::new(),
+            location: String::new(),
+            meeting_time: meeting_time.to_string(),
+        }
+    }
+
+    fn version(version: u32, change: VersionChange, course: &Course, removed: Vec<(String, ScheduledCourse)>) -> CourseVersion {
+        CourseVersion {
+            version,
+            timestamp: Utc::now(),
+            author: Some("admin".to_string()),
+            change,
+            course: course.clone(),
+            removed_entries: removed.into_iter().map(|(username, entry)| RemovedEntry { username, entry }).collect(),
+            restored_from: None,
+        }
+    }
+
+    fn schedule_of_ids(ids: &[u32]) -> Schedule {
+        Schedule {
+            courses: ids.iter().map(|&course_id| ScheduledCourse { course_id, notes: format!("note {}", course_id), slot: String::new() }).collect(),
+            ..Schedule::default()
+        }
+    }
+
+    fn has_course(schedules: &HashMap<String, Schedule>, username: &str, id: u32) -> bool {
+        schedules[username].courses.iter().any(|c| c.course_id == id)
+    }
+
+    #[test]
+    fn undelete_puts_removed_students_back() {
+        let deleted = course(1, "MW 09:00-10:00");
+        let mut courses = vec![deleted.clone(), course(2, "T 09:00-10:00"), course(3, "M 09:30-10:30")];
+        let mut schedules = HashMap::from([
+            ("ana".to_string(), schedule_of_ids(&[1, 2])),
+            ("ben".to_string(), schedule_of_ids(&[1])),
+            ("cy".to_string(), schedule_of_ids(&[1])),
+        ]);
+
+        // Delete the course the way remove_course does
+        let impact = course_impact(&courses, &schedules, 1, None);
+        courses.remove(0);
+        let removed = unschedule_students(&mut schedules, 1, &impact.affected_students);
+        let versions = vec![version(1, VersionChange::Created, &deleted, Vec::new()), version(2, VersionChange::Deleted, &deleted, removed)];
+
+        // Meanwhile cy took a clashing course, and ben got the course back some other way
+        schedules.get_mut("cy").unwrap().courses.push(ScheduledCourse { course_id: 3, notes: String::new(), slot: String::new() });
+        schedules.get_mut("ben").unwrap().courses.push(ScheduledCourse { course_id: 1, notes: String::new(), slot: String::new() });
+
+        courses.insert(0, deleted.clone());
+        let (restored, clashing) = reschedule_removed_entries(&versions, 1, &deleted, &courses, &mut schedules, &[]);
+
+        assert_eq!(restored, ["ana"]);
+        assert!(has_course(&schedules, "ana", 1));
+        assert_eq!(schedules["ana"].courses.last().unwrap().notes, "note 1");
+        assert_eq!(schedules["ben"].courses.iter().filter(|c| c.course_id == 1).count(), 1);
+        assert_eq!(clashing.len(), 1);
+        assert_eq!(clashing[0].username, "cy");
+        assert!(!has_course(&schedules, "cy", 1));
+    }
+
+    #[test]
+    fn diff_lists_changed_fields_or_every_field_of_a_first_version() {
+        let before = course(1, "MW 09:00-10:00");
+        let after = Course { location: "Hall 2".to_string(), ..before.clone() };
+
+        let changes = course_diff(Some(&before), &after);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].field.as_str(), &changes[0].from, &changes[0].to), ("location", &Value::from(""), &Value::from("Hall 2")));
+
+        let changes = course_diff(None, &before);
+        assert_eq!(changes.len(), 7);
+        assert!(changes.iter().all(|c| c.from.is_null()));
+    }
+
+    #[test]
+    fn only_entries_removed_after_the_version_come_back() {
+        let original = course(1, "MW 09:00-10:00");
+        let courses = vec![original.clone()];
+        let mut schedules = HashMap::from([("ana".to_string(), schedule_of_ids(&[])), ("ben".to_string(), schedule_of_ids(&[]))]);
+        let entry = |username: &str| (username.to_string(), ScheduledCourse { course_id: 1, notes: String::new(), slot: String::new() });
+        let versions = vec![
+            version(1, VersionChange::Created, &original, Vec::new()),
+            version(2, VersionChange::Updated, &original, vec![entry("ana")]),
+            version(3, VersionChange::Updated, &original, vec![entry("ben")]),
+        ];
+
+        let (restored, clashing) = reschedule_removed_entries(&versions, 2, &original, &courses, &mut schedules, &[]);
+        assert_eq!(restored, ["ben"]);
+        assert!(clashing.is_empty());
+        assert!(!has_course(&schedules, "ana", 1));
+    }
+}
@@ -0,0 +1,563 @@
+// Bulk import of catalog courses from a CSV or XLSX file. Each row is checked
+// with the same rules as a single new course; a dry run reports every problem
+// without saving, and a real import either adds every row or none of them.
+use std::collections::HashSet;
+use std::io::{Cursor, Read};
+
+use actix_web::{web, HttpResponse};
+use quick_xml::events::Event as XmlEvent;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use tokio_stream::StreamExt;
+use utoipa::{IntoParams, ToSchema};
+use zip::ZipArchive;
+
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::{add_courses, validate_course, AppState, Course, NewCourse};
+
+// Largest file accepted, and the most an XLSX part may expand to
+const MAX_FILE_BYTES: usize = 10 * 1024 * 1024;
+
+// Most data rows in one import
+const MAX_ROWS: usize = 5000;
+
+// Widest sheet read, column IV. Course files only need a few columns, and the
+// limit keeps a sheet with a far-off cell in every row from growing huge rows.
+const MAX_COLUMNS: usize = 256;
+
+// Course fields in the order rows are read into a `NewCourse`
+const FIELDS: [&str; 6] = ["dept_code", "course_number", "instructor", "description", "location", "meeting_time"];
+
+// File formats that can be imported
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportFormat {
+    Csv,
+    Xlsx,
+}
+
+// How to read the uploaded file. Each `*_column` names the header holding that
+// field and defaults to the field name; headers match ignoring case.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub struct ImportOptions {
+    // Guessed from the file contents when missing
+    format: Option<ImportFormat>,
+    // Check every row and report problems without saving anything
+    #[serde(default)]
+    dry_run: bool,
+    dept_code_column: Option<String>,
+    course_number_column: Option<String>,
+    instructor_column: Option<String>,
+    description_column: Option<String>,
+    location_column: Option<String>,
+    meeting_time_column: Option<String>,
+}
+
+// A problem with one row of the file
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct RowError {
+    // Row number as shown in a spreadsheet, the header is row 1
+    row: usize,
+    // Header of the column at fault, if it is one column
+    #[serde(skip_serializing_if = "Option::is_none")]
+    column: Option<String>,
+    message: String,
+}
+
+// Outcome of an import or a dry run
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ImportReport {
+    dry_run: bool,
+    // Data rows read, not counting blank ones
+    rows: usize,
+    // Courses added, empty on a dry run
+    imported: Vec<Course>,
+    errors: Vec<RowError>,
+}
+
+// A parsed file: the header row, then each data row with its row number
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+impl RowError {
+    // Describes a problem with one row, and optionally the column at fault
+    pub fn new(row: usize, column: Option<String>, message: String) -> Self {
+        RowError { row, column, message }
+    }
+}
+
+impl ImportOptions {
+    // Header the user mapped to a field, or the field name itself
+    fn column_for<'a>(&'a self, field: &'a str) -> &'a str {
+        let mapped = match field {
+            "dept_code" => &self.dept_code_column,
+            "course_number" => &self.course_number_column,
+            "instructor" => &self.instructor_column,
+            "description" => &self.description_column,
+            "location" => &self.location_column,
+            _ => &self.meeting_time_column,
+        };
+        mapped.as_deref().unwrap_or(field)
+    }
+}
+
+// Reads the request body, refusing files over the size limit
+pub async fn read_body(mut payload: web::Payload) -> Result<Vec<u8>, ApiError> {
+    let mut body = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| ApiError::InvalidRequest(format!("Failed to read upload: {}", e)))?;
+        if body.len() + chunk.len() > MAX_FILE_BYTES {
+            return Err(ApiError::InvalidRequest(format!("File is larger than {} MB", MAX_FILE_BYTES / (1024 * 1024))));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// Parses a CSV file whose first record is the header
+pub fn read_csv(data: &[u8]) -> Result<Table, ApiError> {
+    let mut reader = csv::ReaderBuilder::new().has_headers(false).flexible(true).from_reader(data);
+    let mut records = reader.records();
+    let headers = match records.next() {
+        Some(record) => record.map_err(|e| ApiError::InvalidRequest(format!("Invalid CSV: {}", e)))?,
+        None => return Err(ApiError::InvalidRequest("The file is empty".to_string())),
+    };
+    let headers = headers.iter().map(|h| h.trim_start_matches('\u{feff}').trim().to_string()).collect();
+
+    let mut rows = Vec::new();
+    for record in records {
+        let record = record.map_err(|e| ApiError::InvalidRequest(format!("Invalid CSV: {}", e)))?;
+        let row = record.position().map_or(rows.len() + 2, |p| p.line() as usize);
+        rows.push((row, record.iter().map(str::to_string).collect()));
+    }
+    Ok(Table { headers, rows })
+}
+
+// Reads one part of an XLSX package, refusing parts that expand past the size limit
+fn read_part(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> Result<Option<String>, ApiError> {
+    let file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(ApiError::InvalidRequest(format!("Invalid XLSX file: {}", e))),
+    };
+    let mut contents = String::new();
+    file.take(MAX_FILE_BYTES as u64 + 1)
+        .read_to_string(&mut contents)
+        .map_err(|e| ApiError::InvalidRequest(format!("Invalid XLSX file: {}", e)))?;
+    if contents.len() > MAX_FILE_BYTES {
+        return Err(ApiError::InvalidRequest("XLSX sheet is too large".to_string()));
+    }
+    Ok(Some(contents))
+}
+
+// Wraps an XML parse failure
+fn xml_error(e: impl std::fmt::Display) -> ApiError {
+    ApiError::InvalidRequest(format!("Invalid XLSX file: {}", e))
+}
+
+// Path inside the package of the first worksheet in the workbook
+fn first_sheet_path(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<String, ApiError> {
+    let workbook = read_part(archive, "xl/workbook.xml")?.ok_or_else(|| xml_error("no workbook"))?;
+    let mut reader = Reader::from_str(&workbook);
+    let mut sheet_rel = None;
+    while sheet_rel.is_none() {
+        match reader.read_event().map_err(xml_error)? {
+            XmlEvent::Start(e) | XmlEvent::Empty(e) if e.local_name().as_ref() == b"sheet" => {
+                sheet_rel = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.local_name().as_ref() == b"id")
+                    .map(|a| String::from_utf8_lossy(&a.value).into_owned());
+            }
+            XmlEvent::Eof => return Err(xml_error("the workbook has no sheets")),
+            _ => {}
+        }
+    }
+
+    let rels = read_part(archive, "xl/_rels/workbook.xml.rels")?.ok_or_else(|| xml_error("no workbook relationships"))?;
+    let mut reader = Reader::from_str(&rels);
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            XmlEvent::Start(e) | XmlEvent::Empty(e) if e.local_name().as_ref() == b"Relationship" => {
+                let attr = |key: &[u8]| e.attributes().flatten().find(|a| a.key.as_ref() == key).map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                if attr(b"Id") == sheet_rel {
+                    let target = attr(b"Target").unwrap_or_default();
+                    // Targets are relative to xl/ unless they start at the package root
+                    return Ok(match target.strip_prefix('/') {
+                        Some(absolute) => absolute.to_string(),
+                        None => format!("xl/{}", target),
+                    });
+                }
+            }
+            XmlEvent::Eof => return Err(xml_error("the first sheet is missing")),
+            _ => {}
+        }
+    }
+}
+
+// Text of every entry in the shared string table
+fn shared_strings(archive: &mut ZipArchive<Cursor<&[u8]>>) -> Result<Vec<String>, ApiError> {
+    let Some(xml) = read_part(archive, "xl/sharedStrings.xml")? else {
+        return Ok(Vec::new());
+    };
+    let mut reader = Reader::from_str(&xml);
+    let mut strings = Vec::new();
+    let mut in_text = false;
+    // Phonetic hints for East Asian text are not part of the value
+    let mut in_phonetic = false;
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            XmlEvent::Start(e) => match e.local_name().as_ref() {
+                b"si" => strings.push(String::new()),
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            XmlEvent::End(e) => match e.local_name().as_ref() {
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            XmlEvent::Text(text) if in_text => {
+                if let Some(last) = strings.last_mut() {
+                    last.push_str(&text.unescape().map_err(xml_error)?);
+                }
+            }
+            XmlEvent::Eof => return Ok(strings),
+            _ => {}
+        }
+    }
+}
+
+// Zero-based column index of a cell reference such as "C12", if it is within `MAX_COLUMNS`
+fn column_index(reference: &str) -> Option<usize> {
+    let letters: Vec<u8> = reference.bytes().take_while(u8::is_ascii_alphabetic).collect();
+    // Excel's last column is XFD, so longer references are malformed
+    if letters.is_empty() || letters.len() > 3 {
+        return None;
+    }
+    let number = letters.iter().fold(0, |n, b| n * 26 + (b.to_ascii_uppercase() - b'A') as usize + 1);
+    (number <= MAX_COLUMNS).then(|| number - 1)
+}
+
+// Shows a numeric cell the way a spreadsheet would, so 101 is "101" rather than "101.0"
+fn format_number(raw: &str) -> String {
+    match raw.parse::<f64>() {
+        Ok(n) if n.fract() == 0.0 && n.abs() < 1e15 => format!("{}", n as i64),
+        _ => raw.to_string(),
+    }
+}
+
+// Parses the first worksheet of an XLSX file whose first non-blank row is the header
+fn read_xlsx(data: &[u8]) -> Result<Table, ApiError> {
+    let mut archive = ZipArchive::new(Cursor::new(data)).map_err(xml_error)?;
+    let strings = shared_strings(&mut archive)?;
+    let path = first_sheet_path(&mut archive)?;
+    let sheet = read_part(&mut archive, &path)?.ok_or_else(|| xml_error("the first sheet is missing"))?;
+
+    let mut reader = Reader::from_str(&sheet);
+    let mut rows: Vec<(usize, Vec<String>)> = Vec::new();
+    // Type, column and value of the cell being read
+    let mut cell: Option<(String, usize, String)> = None;
+    // Column of a cell that doesn't give its reference
+    let mut next_column = 0;
+    let mut in_value = false;
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            XmlEvent::Start(e) if e.local_name().as_ref() == b"row" => {
+                let number = e
+                    .attributes()
+                    .flatten()
+                    .find(|a| a.key.as_ref() == b"r")
+                    .and_then(|a| String::from_utf8_lossy(&a.value).parse().ok())
+                    .unwrap_or_else(|| rows.last().map_or(1, |(n, _)| n + 1));
+                // Rows with nothing in them are dropped as they go, so formatted blank rows don't count against the limit
+                if rows.last().is_some_and(|(_, cells)| cells.is_empty()) {
+                    rows.pop();
+                }
+                if rows.len() > MAX_ROWS {
+                    return Err(ApiError::InvalidRequest(format!("Too many rows, the limit is {}", MAX_ROWS)));
+                }
+                rows.push((number, Vec::new()));
+                next_column = 0;
+            }
+            XmlEvent::Start(e) if e.local_name().as_ref() == b"c" => {
+                let attr = |key: &[u8]| e.attributes().flatten().find(|a| a.key.as_ref() == key).map(|a| String::from_utf8_lossy(&a.value).into_owned());
+                let column = match attr(b"r") {
+                    Some(reference) => column_index(&reference)
+                        .ok_or_else(|| xml_error(format!("cell reference '{}' is invalid or past column {}", reference, MAX_COLUMNS)))?,
+                    None if next_column < MAX_COLUMNS => next_column,
+                    None => return Err(xml_error(format!("the sheet has more than {} columns", MAX_COLUMNS))),
+                };
+                next_column = column + 1;
+                cell = Some((attr(b"t").unwrap_or_default(), column, String::new()));
+            }
+            XmlEvent::Start(e) if matches!(e.local_name().as_ref(), b"v" | b"t") => in_value = true,
+            XmlEvent::End(e) if matches!(e.local_name().as_ref(), b"v" | b"t") => in_value = false,
+            XmlEvent::Text(text) if in_value => {
+                if let Some((_, _, value)) = cell.as_mut() {
+                    value.push_str(&text.unescape().map_err(xml_error)?);
+                }
+            }
+            XmlEvent::End(e) if e.local_name().as_ref() == b"c" => {
+                let (Some((kind, column, raw)), Some((_, cells))) = (cell.take(), rows.last_mut()) else {
+                    continue;
+                };
+                // Formatted cells without a value don't widen the row
+                if raw.is_empty() {
+                    continue;
+                }
+                let value = match kind.as_str() {
+                    "s" => raw.parse::<usize>().ok().and_then(|i| strings.get(i).cloned()).unwrap_or_default(),
+                    "b" => if raw == "1" { "TRUE" } else { "FALSE" }.to_string(),
+                    "str" | "inlineStr" | "e" => raw,
+                    _ => format_number(&raw),
+                };
+                if cells.len() <= column {
+                    cells.resize(column + 1, String::new());
+                }
+                cells[column] = value;
+            }
+            XmlEvent::Eof => break,
+            _ => {}
+        }
+    }
+
+    rows.retain(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty()));
+    if rows.is_empty() {
+        return Err(ApiError::InvalidRequest("The first sheet is empty".to_string()));
+    }
+    let (_, headers) = rows.remove(0);
+    let headers = headers.into_iter().map(|h| h.trim().to_string()).collect();
+    Ok(Table { headers, rows })
+}
+
+// Checks every row against the catalog and builds the courses they describe, collecting all problems
+fn check_rows(catalog: &[Course], table: Table, options: &ImportOptions) -> Result<(usize, Vec<NewCourse>, Vec<RowError>), ApiError> {
+    // Find the column for each field before looking at any rows
+    let mut columns = Vec::new();
+    for field in FIELDS {
+        let header = options.column_for(field);
+        let index = table.headers.iter().position(|h| h.eq_ignore_ascii_case(header.trim())).ok_or_else(|| {
+            ApiError::InvalidRequest(format!("No column named '{}' for {}; columns are: {}", header, field, table.headers.join(", ")))
+        })?;
+        columns.push((index, table.headers[index].clone()));
+    }
+
+    let rows: Vec<(usize, Vec<String>)> = table.rows.into_iter().filter(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty())).collect();
+    if rows.is_empty() {
+        return Err(ApiError::InvalidRequest("The file has no course rows".to_string()));
+    }
+    if rows.len() > MAX_ROWS {
+        return Err(ApiError::InvalidRequest(format!("Too many rows, the limit is {}", MAX_ROWS)));
+    }
+
+    // The same section twice is almost always a file imported again
+    let mut seen: HashSet<(String, String, String)> = catalog
+        .iter()
+        .map(|c| (c.dept_code.clone(), c.course_number.clone(), c.meeting_time.clone()))
+        .collect();
+
+    let count = rows.len();
+    let mut courses = Vec::new();
+    let mut errors = Vec::new();
+    for (row, cells) in rows {
+        let value = |field: usize| cells.get(columns[field].0).map(|v| v.trim().to_string()).unwrap_or_default();
+        let course = NewCourse {
+            dept_code: value(0),
+            course_number: value(1),
+            instructor: value(2),
+            description: value(3),
+            location: value(4),
+            meeting_time: value(5),
+        };
+
+        if course.course_number.is_empty() {
+            errors.push(RowError { row, column: Some(columns[1].1.clone()), message: "Course number is required".to_string() });
+            continue;
+        }
+        if let Err(e) = validate_course(&course) {
+            let column = match &e {
+                ApiError::Validation { field, .. } => FIELDS.iter().position(|f| f == field).map(|i| columns[i].1.clone()),
+                _ => None,
+            };
+            errors.push(RowError { row, column, message: e.to_string() });
+            continue;
+        }
+        let key = (course.dept_code.clone(), course.course_number.clone(), course.meeting_time.clone());
+        if !seen.insert(key) {
+            errors.push(RowError {
+                row,
+                column: None,
+                message: format!("{} {} at {} is already in the catalog or earlier in the file", course.dept_code, course.course_number, course.meeting_time),
+            });
+            continue;
+        }
+        courses.push(course);
+    }
+    Ok((count, courses, errors))
+}
+
+// POST /api/courses/import
+#[utoipa::path(
+    post,
+    path = "/api/courses/import",
+    tag = "courses",
+    params(ImportOptions),
+    request_body(content = Vec<u8>, description = "CSV or XLSX file with a header row", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Courses imported, or the problems found on a dry run", body = ImportReport),
+        (status = 400, description = "Unreadable file, missing columns, or rows that failed validation", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn import_courses(admin: AuthUser, options: web::Query<ImportOptions>, payload: web::Payload, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let data = read_body(payload).await?;
+    // XLSX files are zip archives, which start with "PK"
+    let format = options.format.unwrap_or(if data.starts_with(b"PK\x03\x04") { ImportFormat::Xlsx } else { ImportFormat::Csv });
+    let table = match format {
+        ImportFormat::Csv => read_csv(&data)?,
+        ImportFormat::Xlsx => read_xlsx(&data)?,
+    };
+
+    // Hold the catalog from the duplicate check to the insert, so two imports of one file can't both add it
+    let mut catalog = state.courses.lock().unwrap();
+    let (rows, courses, errors) = check_rows(&catalog, table, &options)?;
+    if options.dry_run {
+        return Ok(HttpResponse::Ok().json(ImportReport { dry_run: true, rows, imported: Vec::new(), errors }));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ImportFailed(errors));
+    }
+
+    let imported = add_courses(&state, &admin.username, &mut catalog, courses)?;
+    Ok(HttpResponse::Ok().json(ImportReport { dry_run: false, rows, imported, errors }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    // A minimal XLSX package with one sheet and, optionally, a shared string table
+    fn xlsx(sheet_data: &str, shared_strings: Option<&str>) -> Vec<u8> {
+        let mut parts = vec![
+            (
+                "xl/workbook.xml",
+                r#"<workbook xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Courses" sheetId="1" r:id="rId1"/></sheets></workbook>"#.to_string(),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships><Relationship Id="rId1" Target="worksheets/sheet1.xml"/></Relationships>"#.to_string(),
+            ),
+            ("xl/worksheets/sheet1.xml", format!("<worksheet><sheetData>{}</sheetData></worksheet>", sheet_data)),
+        ];
+        if let Some(strings) = shared_strings {
+            parts.push(("xl/sharedStrings.xml", format!("<sst>{}</sst>", strings)));
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in parts {
+            zip.start_file(name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    // The message of a failed read
+    fn read_error(data: &[u8]) -> String {
+        match read_xlsx(data) {
+            Ok(_) => panic!("expected the sheet to be rejected"),
+            Err(e) => e.message(),
+        }
+    }
+
+    #[test]
+    fn column_index_reads_letters_within_the_limit() {
+        assert_eq!(column_index("A1"), Some(0));
+        assert_eq!(column_index("z9"), Some(25));
+        assert_eq!(column_index("AA10"), Some(26));
+        assert_eq!(column_index("IV1"), Some(MAX_COLUMNS - 1));
+        assert_eq!(column_index("IW1"), None);
+        assert_eq!(column_index("XFD1"), None);
+        assert_eq!(column_index("AAAAAAAAAAAAAA1"), None);
+        assert_eq!(column_index("12"), None);
+    }
+
+    #[test]
+    fn shared_strings_are_looked_up_and_numbers_shown_plainly() {
+        let strings = "<si><t>dept_code</t></si>\
+                       <si><r><t>course_</t></r><r><t>number</t></r></si>\
+                       <si><t>CS</t><rPh><t>ignored</t></rPh></si>";
+        let sheet = r#"<row r="1"><c r="A1" t="s"><v>0</v></c><c r="B1" t="s"><v>1</v></c></row>
+                       <row r="2"><c r="A2" t="s"><v>2</v></c><c r="B2"><v>1001</v></c><c r="C2" t="inlineStr"><is><t>x &amp; y</t></is></c><c r="D2" t="b"><v>1</v></c></row>"#;
+        let table = read_xlsx(&xlsx(sheet, Some(strings))).unwrap();
+
+        assert_eq!(table.headers, ["dept_code", "course_number"]);
+        assert_eq!(table.rows, [(2, vec!["CS".to_string(), "1001".to_string(), "x & y".to_string(), "TRUE".to_string()])]);
+    }
+
+    #[test]
+    fn sparse_and_out_of_order_cells_land_in_their_columns() {
+        let sheet = r#"<row r="1"><c t="inlineStr"><is><t>a</t></is></c><c t="inlineStr"><is><t>b</t></is></c><c t="inlineStr"><is><t>c</t></is></c></row>
+                       <row r="3" s="1"><c r="B3" s="2"/></row>
+                       <row r="4"><c r="C4"><v>3</v></c><c r="A4"><v>1</v></c><c r="IV4" s="2"/></row>"#;
+        let table = read_xlsx(&xlsx(sheet, None)).unwrap();
+
+        assert_eq!(table.headers, ["a", "b", "c"]);
+        // The blank formatted row is dropped and the empty cell at IV doesn't widen row 4
+        assert_eq!(table.rows, [(4, vec!["1".to_string(), String::new(), "3".to_string()])]);
+    }
+
+    #[test]
+    fn malformed_and_out_of_range_references_are_rejected() {
+        let overflow = r#"<row r="1"><c r="AAAAAAAAAAAAAA1"><v>1</v></c></row>"#;
+        assert!(read_error(&xlsx(overflow, None)).contains("AAAAAAAAAAAAAA1"));
+
+        let too_wide = r#"<row r="1"><c r="IW1"><v>1</v></c></row>"#;
+        assert!(read_error(&xlsx(too_wide, None)).contains("IW1"));
+
+        let unreferenced: String = std::iter::repeat_n("<c><v>1</v></c>", MAX_COLUMNS + 1).collect();
+        assert!(read_error(&xlsx(&format!("<row>{}</row>", unreferenced), None)).contains("columns"));
+    }
+
+    #[test]
+    fn too_many_rows_are_rejected_while_reading() {
+        let rows: String = (1..=MAX_ROWS + 2).map(|r| format!(r#"<row r="{0}"><c r="A{0}"><v>{0}</v></c></row>"#, r)).collect();
+        assert!(read_error(&xlsx(&rows, None)).contains("Too many rows"));
+
+        let rows: String = (1..=MAX_ROWS + 1).map(|r| format!(r#"<row r="{0}"><c r="A{0}"><v>{0}</v></c></row>"#, r)).collect();
+        assert_eq!(read_xlsx(&xlsx(&rows, None)).unwrap().rows.len(), MAX_ROWS);
+    }
+
+    #[test]
+    fn rows_already_in_the_catalog_or_the_file_are_rejected() {
+        let catalog = vec![Course {
+            id: 1,
+            dept_code: "CS".to_string(),
+            course_number: "101".to_string(),
+            instructor: "Staff".to_string(),
+            description: String::new(),
+            location: String::new(),
+            meeting_time: "MW 09:00-10:00".to_string(),
+        }];
+        let row = |number: &str, time: &str| vec!["CS".to_string(), number.to_string(), "Staff".to_string(), String::new(), String::new(), time.to_string()];
+        let table = Table {
+            headers: FIELDS.iter().map(|f| f.to_string()).collect(),
+            rows: vec![(2, row("101", "MW 09:00-10:00")), (3, row("102", "T 09:00-10:00")), (4, row("102", "T 09:00-10:00"))],
+        };
+
+        let (count, courses, errors) = check_rows(&catalog, table, &ImportOptions::default()).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(courses.iter().map(|c| c.course_number.as_str()).collect::<Vec<_>>(), ["102"]);
+        assert_eq!(errors.iter().map(|e| e.row).collect::<Vec<_>>(), [2, 4]);
+    }
+}
@@ -0,0 +1,159 @@
+// Routes for instructors. An instructor account is linked to the courses whose
+// `instructor` field matches its `instructor_name`; it can list those courses,
+// see who is enrolled, and edit their descriptions but nothing else.
+use actix_web::{web, HttpResponse, Responder};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::events::{publish, Event};
+use super::history::{record_version, VersionChange};
+use super::notifications::{course_label, notify, NotificationKind};
+use super::{save_courses, AppState, Course, CourseDescription, ScheduleStatus};
+
+// One student with a course in their schedule, registered once the schedule is approved
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RosterEntry {
+    username: String,
+    status: ScheduleStatus,
+}
+
+// Looks up the name an instructor appears under in the catalog
+fn instructor_name(state: &AppState, user: &AuthUser) -> Result<String, ApiError> {
+    let users = state.users.lock().unwrap();
+    users
+        .iter()
+        .find(|u| u.username == user.username)
+        .and_then(|u| u.instructor_name.clone())
+        .ok_or_else(|| ApiError::Forbidden("This account is not linked to any courses".to_string()))
+}
+
+// Returns a course if the instructor teaches it
+fn taught_course(state: &AppState, user: &AuthUser, course_id: u32) -> Result<Course, ApiError> {
+    let name = instructor_name(state, user)?;
+    let courses = state.courses.lock().unwrap();
+    let course = courses.iter().find(|c| c.id == course_id).ok_or(ApiError::CourseNotFound)?;
+    if course.instructor != name {
+        return Err(ApiError::Forbidden("You do not teach this course".to_string()));
+    }
+    Ok(course.clone())
+}
+
+// Lists the students with a course in their schedule, sorted by username
+fn roster(state: &AppState, course_id: u32) -> Vec<RosterEntry> {
+    let schedules = state.schedules.lock().unwrap();
+    let mut entries: Vec<RosterEntry> = schedules
+        .iter()
+        .filter(|(_, schedule)| schedule.courses.iter().any(|c| c.course_id == course_id))
+        .map(|(username, schedule)| RosterEntry { username: username.clone(), status: schedule.status })
+        .collect();
+    entries.sort_by(|a, b| a.username.cmp(&b.username));
+    entries
+}
+
+// Writes a roster as CSV with a header line
+fn roster_csv(entries: &[RosterEntry]) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["username", "status"])?;
+    for entry in entries {
+        writer.write_record([entry.username.clone(), entry.status.to_string()])?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+// GET /api/me/courses
+#[utoipa::path(
+    get,
+    path = "/api/me/courses",
+    tag = "instructor",
+    responses(
+        (status = 200, description = "Courses taught by the logged-in instructor", body = Vec<Course>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn my_courses(user: AuthUser, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let name = instructor_name(&state, &user)?;
+    let courses = state.courses.lock().unwrap();
+    let taught: Vec<&Course> = courses.iter().filter(|c| c.instructor == name).collect();
+    Ok(HttpResponse::Ok().json(taught))
+}
+
+// PATCH /api/me/courses/{id}
+#[utoipa::path(
+    patch,
+    path = "/api/me/courses/{id}",
+    tag = "instructor",
+    params(("id" = u32, Path, description = "Course ID")),
+    request_body = CourseDescription,
+    responses(
+        (status = 200, description = "Description updated", body = Course),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn patch_my_course(user: AuthUser, id: web::Path<u32>, body: web::Json<CourseDescription>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    taught_course(&state, &user, id)?;
+
+    // Only the description changes, so students keep the course in their schedules
+    let mut courses = state.courses.lock().unwrap();
+    let course = courses.iter_mut().find(|c| c.id == id).ok_or(ApiError::CourseNotFound)?;
+    let before = course.clone();
+    course.description = body.into_inner().description;
+    let course = course.clone();
+    save_courses(&courses).map_err(|e| ApiError::storage("courses", e))?;
+    record_version(&state, &user.username, VersionChange::Updated, Some(&before), &course, Vec::new())?;
+    audit::record(&state, &user.username, "course.update", format!("course:{}", id), Some(&before), Some(&course));
+    drop(courses);
+    publish(&state, Event::CourseUpdated { course: course.clone() });
+
+    let students: Vec<String> = roster(&state, id).into_iter().map(|entry| entry.username).collect();
+    let message = format!("The description of {} was updated by its instructor", course_label(&course));
+    notify(&state, &students, NotificationKind::CourseUpdated, Some(id), &message);
+    Ok(HttpResponse::Ok().json(course))
+}
+
+// GET /api/me/courses/{id}/roster
+#[utoipa::path(
+    get,
+    path = "/api/me/courses/{id}/roster",
+    tag = "instructor",
+    params(("id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "Students enrolled in the course", body = Vec<RosterEntry>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+    )
+)]
+pub async fn course_roster(user: AuthUser, id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let course = taught_course(&state, &user, id.into_inner())?;
+    Ok(HttpResponse::Ok().json(roster(&state, course.id)))
+}
+
+// GET /api/me/courses/{id}/roster.csv
+#[utoipa::path(
+    get,
+    path = "/api/me/courses/{id}/roster.csv",
+    tag = "instructor",
+    params(("id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "Enrolled students as CSV", content_type = "text/csv", body = String),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+    )
+)]
+pub async fn export_roster(user: AuthUser, id: web::Path<u32>, state: web::Data<AppState>) -> Result<impl Responder, ApiError> {
+    let course = taught_course(&state, &user, id.into_inner())?;
+
+    let csv = roster_csv(&roster(&state, course.id)).map_err(|e| ApiError::Storage(format!("Failed to write roster: {}", e)))?;
+
+    let filename = format!("{}-{}-roster.csv", course.dept_code, course.course_number);
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(csv))
+}
@@ -0,0 +1,337 @@
+// Email delivery for notifications. A `Mailer` sends one message; mail.json
+// picks the SMTP transport or a local maildir for testing, and can replace the
+// built-in templates. Users opt in per notification kind and only get email
+// for the kinds they chose.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Address, Message, SmtpTransport, Transport};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::notifications::NotificationKind;
+use super::{save_users, AppState};
+
+// Error from building or delivering a message
+pub type MailError = Box<dyn std::error::Error + Send + Sync>;
+
+// Something that can deliver an email
+pub trait Mailer: Send + Sync {
+    fn send(&self, message: Message) -> Result<(), MailError>;
+}
+
+// Delivers mail through an SMTP server
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, message: Message) -> Result<(), MailError> {
+        self.transport.send(&message)?;
+        Ok(())
+    }
+}
+
+// Writes each message as a file in a maildir, for local testing
+pub struct MaildirMailer {
+    path: PathBuf,
+    counter: AtomicU64,
+}
+
+impl Mailer for MaildirMailer {
+    fn send(&self, message: Message) -> Result<(), MailError> {
+        // Write into tmp/ and rename into new/ so readers never see a partial message
+        let tmp = self.path.join("tmp");
+        let new = self.path.join("new");
+        fs::create_dir_all(&tmp)?;
+        fs::create_dir_all(&new)?;
+        fs::create_dir_all(self.path.join("cur"))?;
+
+        let now = Utc::now();
+        let name = format!(
+            "{}.M{}P{}Q{}.course_scheduler",
+            now.timestamp(),
+            now.timestamp_subsec_micros(),
+            std::process::id(),
+            self.counter.fetch_add(1, Ordering::Relaxed),
+        );
+        fs::write(tmp.join(&name), message.formatted())?;
+        fs::rename(tmp.join(&name), new.join(&name))?;
+        Ok(())
+    }
+}
+
+// How the SMTP connection is secured
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum SmtpSecurity {
+    // TLS from the start, usually port 465
+    #[default]
+    Tls,
+    // Upgrade a plain connection, usually port 587
+    Starttls,
+    // No encryption, only for local test servers
+    None,
+}
+
+// Where mail goes
+#[derive(Deserialize, Debug)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+enum TransportConfig {
+    Smtp {
+        host: String,
+        port: Option<u16>,
+        #[serde(default)]
+        security: SmtpSecurity,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    Maildir {
+        path: String,
+    },
+}
+
+// Subject and body of one kind of message. `{username}` and `{message}` are filled in.
+#[derive(Deserialize, Debug, Clone)]
+struct MailTemplate {
+    subject: String,
+    body: String,
+}
+
+// Contents of mail.json
+#[derive(Deserialize, Debug)]
+struct MailConfig {
+    from: String,
+    #[serde(flatten)]
+    transport: TransportConfig,
+    // Replacements for the built-in templates, by notification kind
+    #[serde(default)]
+    templates: HashMap<NotificationKind, MailTemplate>,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            from: "Course Scheduler <scheduler@localhost>".to_string(),
+            transport: TransportConfig::Maildir { path: "maildir".to_string() },
+            templates: HashMap::new(),
+        }
+    }
+}
+
+// The configured mailer along with the sender and templates it uses
+pub struct MailSettings {
+    mailer: Arc<dyn Mailer>,
+    from: Mailbox,
+    templates: HashMap<NotificationKind, MailTemplate>,
+}
+
+// A user's email address and the notifications they want by email
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
+pub struct EmailSettings {
+    email: Option<String>,
+    // Kinds of notification that are also sent by email
+    #[serde(default)]
+    notify: Vec<NotificationKind>,
+}
+
+// Built-in template for each kind of notification
+fn default_template(kind: NotificationKind) -> MailTemplate {
+    let subject = match kind {
+        NotificationKind::CourseUpdated => "A course in your schedule changed",
+        NotificationKind::CourseDropped => "A course was removed from your schedule",
+        NotificationKind::CourseDeleted => "A course in your schedule was cancelled",
+        NotificationKind::CourseRestored => "A course in your schedule was restored to an earlier version",
+        NotificationKind::ScheduleSubmitted => "An advisee submitted their schedule",
+        NotificationKind::ScheduleApproved => "Your schedule was approved",
+        NotificationKind::ScheduleRejected => "Your schedule needs changes",
+        NotificationKind::OverrideGranted => "Your override request was granted",
+        NotificationKind::OverrideDenied => "Your override request was denied",
+    };
+    MailTemplate {
+        subject: subject.to_string(),
+        body: "Hello {username},\n\n{message}.\n\nSign in to the course scheduler for details.\n".to_string(),
+    }
+}
+
+// Fills in a template's placeholders
+fn render(template: &str, username: &str, message: &str) -> String {
+    template.replace("{username}", username).replace("{message}", message)
+}
+
+// Builds the mailer described by mail.json, falling back to a local maildir
+pub fn load_mail_settings() -> MailSettings {
+    let config = match fs::read_to_string("mail.json") {
+        Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+            println!("Invalid mail.json, writing mail to ./maildir instead: {}", e);
+            MailConfig::default()
+        }),
+        Err(_) => MailConfig::default(),
+    };
+
+    let from = config.from.parse().unwrap_or_else(|e| {
+        println!("Invalid sender in mail.json ({}), using scheduler@localhost", e);
+        Mailbox::new(None, Address::new("scheduler", "localhost").unwrap())
+    });
+    let mailer: Arc<dyn Mailer> = match config.transport {
+        TransportConfig::Smtp { host, port, security, username, password } => {
+            let builder = match security {
+                SmtpSecurity::Tls => SmtpTransport::relay(&host),
+                SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&host),
+                SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(&host)),
+            };
+            match builder {
+                Ok(mut builder) => {
+                    if let Some(port) = port {
+                        builder = builder.port(port);
+                    }
+                    if let (Some(username), Some(password)) = (username, password) {
+                        builder = builder.credentials(Credentials::new(username, password));
+                    }
+                    Arc::new(SmtpMailer { transport: builder.build() })
+                }
+                Err(e) => {
+                    println!("Failed to set up SMTP for {}, writing mail to ./maildir instead: {}", host, e);
+                    Arc::new(MaildirMailer { path: PathBuf::from("maildir"), counter: AtomicU64::new(0) })
+                }
+            }
+        }
+        TransportConfig::Maildir { path } => Arc::new(MaildirMailer { path: PathBuf::from(path), counter: AtomicU64::new(0) }),
+    };
+
+    MailSettings { mailer, from, templates: config.templates }
+}
+
+// Checks that an email address can be delivered to
+pub fn validate_email(email: &str) -> Result<(), ApiError> {
+    email
+        .parse::<Address>()
+        .map(|_| ())
+        .map_err(|_| ApiError::validation("email", "Not a valid email address"))
+}
+
+// Emails a notification to each of the given users who opted in to this kind
+pub fn send_notification_emails(state: &AppState, usernames: &[String], kind: NotificationKind, message: &str) {
+    let recipients: Vec<(String, String)> = {
+        let users = state.users.lock().unwrap();
+        users
+            .iter()
+            .filter(|u| usernames.contains(&u.username) && u.email_notifications.contains(&kind))
+            .filter_map(|u| u.email.clone().map(|email| (u.username.clone(), email)))
+            .collect()
+    };
+    if recipients.is_empty() {
+        return;
+    }
+
+    let settings = &state.mail;
+    let template = settings.templates.get(&kind).cloned().unwrap_or_else(|| default_template(kind));
+    let mut messages = Vec::new();
+    for (username, email) in recipients {
+        let built = email.parse::<Mailbox>().map_err(MailError::from).and_then(|to| {
+            Message::builder()
+                .from(settings.from.clone())
+                .to(to)
+                .subject(render(&template.subject, &username, message))
+                .header(ContentType::TEXT_PLAIN)
+                .body(render(&template.body, &username, message))
+                .map_err(MailError::from)
+        });
+        match built {
+            Ok(built) => messages.push(built),
+            Err(e) => println!("Failed to build email for {}: {}", username, e),
+        }
+    }
+
+    deliver(settings, messages);
+}
+
+// Emails one message to an address, e.g. a password reset code
+pub fn send_email(state: &AppState, to: &str, subject: &str, body: &str) -> Result<(), MailError> {
+    let message = Message::builder()
+        .from(state.mail.from.clone())
+        .to(to.parse::<Mailbox>()?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body.to_string())?;
+    deliver(&state.mail, vec![message]);
+    Ok(())
+}
+
+// Sends messages through the configured mailer
+fn deliver(settings: &MailSettings, messages: Vec<Message>) {
+    // Delivery can be slow, so it happens off the request thread and failures are only logged
+    let mailer = Arc::clone(&settings.mailer);
+    std::thread::spawn(move || {
+        for message in messages {
+            if let Err(e) = mailer.send(message) {
+                println!("Failed to send email: {}", e);
+            }
+        }
+    });
+}
+
+// GET /api/me/email
+#[utoipa::path(
+    get,
+    path = "/api/me/email",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Email address and notification preferences", body = EmailSettings),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+    )
+)]
+pub async fn get_email_settings(user: AuthUser, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let users = state.users.lock().unwrap();
+    let account = users.iter().find(|u| u.username == user.username).ok_or(ApiError::UserNotFound)?;
+    Ok(HttpResponse::Ok().json(EmailSettings { email: account.email.clone(), notify: account.email_notifications.clone() }))
+}
+
+// PUT /api/me/email
+#[utoipa::path(
+    put,
+    path = "/api/me/email",
+    tag = "notifications",
+    request_body = EmailSettings,
+    responses(
+        (status = 200, description = "Preferences saved", body = EmailSettings),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn put_email_settings(user: AuthUser, body: web::Json<EmailSettings>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut settings = body.into_inner();
+    settings.email = settings.email.map(|e| e.trim().to_string()).filter(|e| !e.is_empty());
+    match &settings.email {
+        Some(email) => validate_email(email)?,
+        None if !settings.notify.is_empty() => {
+            return Err(ApiError::validation("email", "Set an email address to receive notifications by email"));
+        }
+        None => {}
+    }
+    settings.notify.sort();
+    settings.notify.dedup();
+
+    let mut users = state.users.lock().unwrap();
+    let account = users.iter_mut().find(|u| u.username == user.username).ok_or(ApiError::UserNotFound)?;
+    let before = account.clone();
+    account.email = settings.email.clone();
+    account.email_notifications = settings.notify.clone();
+    let after = account.clone();
+
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    audit::record(&state, &user.username, "user.email_settings", format!("user:{}", user.username), Some(&before), Some(&after));
+    Ok(HttpResponse::Ok().json(settings))
+}
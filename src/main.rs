@@ -1,12 +1,54 @@
+mod approval;
+mod audit;
+mod auth;
+mod error;
+mod events;
+mod export;
+mod history;
+mod import;
+mod instructor;
+mod mailer;
+mod notifications;
+mod openapi;
+mod overlay;
+mod overrides;
+mod passwords;
+mod provisioning;
+mod resources;
+mod rooms;
+mod setup;
+mod share;
+mod throttle;
+mod two_factor;
+
 use actix_files::Files;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_web::middleware::{from_fn, DefaultHeaders};
+use actix_web::http::Method;
+use actix_web::{web, App, FromRequest, Handler, HttpRequest, HttpResponse, HttpServer, Responder, Route};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::sync::Mutex;
-use open;
+use utoipa::{IntoParams, ToSchema};
+
+use auth::{require_role, Admin, Advisor, AuthUser, Instructor, Role, RoleMarker, Student};
+use error::{ApiError, ApiErrorBody, LegacyError};
+use audit::{load_audit_log, AuditEntry};
+use events::{enrollment_changed, event_channel, publish, schedule_changed, Event, Published};
+use history::{highest_course_id, load_course_history, record_version, CourseVersion, VersionChange};
+use mailer::{load_mail_settings, validate_email, MailSettings};
+use notifications::{clear_notifications, course_label, load_notifications, notify, Notification, NotificationKind};
+use overrides::{has_granted_override, OverrideRequest, OverrideRule};
+use passwords::{load_password_resets, revoke_password_resets, validate_password_strength, PasswordReset};
+use provisioning::Activation;
+use rooms::{forget_user, load_rooms, PlanningRoom, RoomChannels};
+use setup::setup_token;
+use share::{load_share_links, revoke_share_links, ShareLink};
+use throttle::{check_login_allowed, record_login_failure, record_login_success, LoginAttempts};
+use two_factor::{load_two_factor, remove_two_factor, start_two_factor_login, two_factor_enabled, PendingLogin, TwoFactor};
 
 // Represents a course with all its details
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 struct Course {
     id: u32,
     dept_code: String,
@@ -18,7 +60,7 @@ struct Course {
 }
 
 // Data for creating a new course (no ID since it's assigned automatically)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct NewCourse {
     dept_code: String,
     course_number: String,
@@ -29,7 +71,7 @@ struct NewCourse {
 }
 
 // For updating an existing course, includes the ID
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct UpdateCourse {
     id: u32,
     dept_code: String,
@@ -41,38 +83,184 @@ struct UpdateCourse {
 }
 
 // A course that's been scheduled with additional info
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 struct ScheduledCourse {
     course_id: u32,
     notes: String,
     slot: String,
 }
 
+// Where a schedule is in the advisor approval workflow
+#[derive(Serialize, Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ScheduleStatus {
+    #[default]
+    Draft,
+    Submitted,
+    Approved,
+    Rejected,
+}
+
+impl std::fmt::Display for ScheduleStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScheduleStatus::Draft => f.write_str("draft"),
+            ScheduleStatus::Submitted => f.write_str("submitted"),
+            ScheduleStatus::Approved => f.write_str("approved"),
+            ScheduleStatus::Rejected => f.write_str("rejected"),
+        }
+    }
+}
+
+// A comment left on a schedule when it is submitted or reviewed
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+struct ScheduleComment {
+    author: String,
+    status: ScheduleStatus,
+    text: String,
+}
+
 // Holds the user's schedule
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Default, Clone, ToSchema)]
 struct Schedule {
     courses: Vec<ScheduledCourse>,
+    // Only an approved schedule counts as registered
+    #[serde(default)]
+    status: ScheduleStatus,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    comments: Vec<ScheduleComment>,
+}
+
+impl Schedule {
+    // Sends the schedule back to draft after its courses change, so it needs approval again
+    fn mark_changed(&mut self) {
+        self.status = ScheduleStatus::Draft;
+    }
 }
 
 // User info for login and roles
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
 struct User {
     username: String,
     password: String,
-    role: String,  // Either "admin" or "student"
+    role: Role,
+    // For instructors, their name as it appears in `Course.instructor`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    instructor_name: Option<String>,
+    // For students, the username of the advisor who approves their schedule
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    advisor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    // Kinds of notification the user also wants by email
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    email_notifications: Vec<NotificationKind>,
+    // Set when someone else picked the password; the user has to choose their own before doing anything else
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    must_change_password: bool,
+    // Pending activation of a provisioned account, which has no usable password until then
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    activation: Option<Activation>,
 }
 
 // Login request data
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, ToSchema)]
 struct LoginRequest {
     username: String,
     password: String,
 }
 
 // Response after successful login
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct LoginResponse {
-    role: String,
+    role: Role,
+    // Every other request fails until the password is changed with POST /api/me/password
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    must_change_password: bool,
+    // Set instead of starting a session when the account uses two-factor authentication; send it with a code to POST /api/login/two-factor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    two_factor_challenge: Option<String>,
+    // Admins have to set up two-factor authentication before doing anything else
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    two_factor_setup_required: bool,
+}
+
+// Partial update for a course, only the given fields change
+#[derive(Deserialize, Debug, Default, ToSchema)]
+struct CoursePatch {
+    dept_code: Option<String>,
+    course_number: Option<String>,
+    instructor: Option<String>,
+    description: Option<String>,
+    location: Option<String>,
+    meeting_time: Option<String>,
+}
+
+// Options for changing or deleting a course
+#[derive(Deserialize, Debug, Default, IntoParams)]
+struct CourseChangeOptions {
+    // Report who would be affected without changing anything
+    #[serde(default)]
+    dry_run: bool,
+    // Keep students enrolled when the new version doesn't conflict with their schedule
+    #[serde(default)]
+    keep_enrolled: bool,
+}
+
+// A student whose schedule would conflict with a changed course
+#[derive(Serialize, Debug, ToSchema)]
+struct StudentConflicts {
+    username: String,
+    conflicts: Vec<ConflictDetail>,
+}
+
+// Who a course change or deletion would affect
+#[derive(Serialize, Debug, ToSchema)]
+struct CourseImpact {
+    // Students with the course in their schedule
+    affected_students: Vec<String>,
+    // Students for whom the changed course would clash with a scheduled course it doesn't already clash with
+    conflicting_students: Vec<StudentConflicts>,
+}
+
+// What replacing or updating a course returns: the saved course, or on a dry run the impact of the change
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(untagged)]
+enum CourseChange {
+    Saved(Course),
+    DryRun(CourseImpact),
+}
+
+// New description for a course, the only field an instructor may edit
+#[derive(Deserialize, Debug, ToSchema)]
+struct CourseDescription {
+    description: String,
+}
+
+// Password for creating or replacing a student account
+#[derive(Deserialize, Debug, ToSchema)]
+struct UserPassword {
+    password: String,
+}
+
+// Partial update for a student account
+#[derive(Deserialize, Debug, Default, ToSchema)]
+struct UserPatch {
+    password: Option<String>,
+}
+
+// Partial update for a schedule entry
+#[derive(Deserialize, Debug, Default, ToSchema)]
+struct SchedulePatch {
+    notes: Option<String>,
+    slot: Option<String>,
+}
+
+// Request to replace one scheduled course with another
+#[derive(Deserialize, Debug, ToSchema)]
+struct SwapRequest {
+    drop_course_id: u32,
+    add_course_id: u32,
 }
 
 // Tracks the current session
@@ -81,18 +269,27 @@ struct Session {
     username: String,
 }
 
-// Error message structure
-#[derive(Serialize)]
-struct ErrorResponse {
-    error: String,
-}
-
 // Application state shared across requests
 struct AppState {
     courses: Mutex<Vec<Course>>,
-    schedule: Mutex<Schedule>,
+    schedules: Mutex<HashMap<String, Schedule>>,
     users: Mutex<Vec<User>>,
     session: Mutex<Option<Session>>,
+    overrides: Mutex<Vec<OverrideRequest>>,
+    audit: Mutex<Vec<AuditEntry>>,
+    course_history: Mutex<HashMap<u32, Vec<CourseVersion>>>,
+    notifications: Mutex<Vec<Notification>>,
+    mail: MailSettings,
+    events: tokio::sync::broadcast::Sender<Published>,
+    rooms: Mutex<Vec<PlanningRoom>>,
+    room_channels: Mutex<RoomChannels>,
+    share_links: Mutex<Vec<ShareLink>>,
+    password_resets: Mutex<Vec<PasswordReset>>,
+    login_attempts: Mutex<LoginAttempts>,
+    two_factor: Mutex<HashMap<String, TwoFactor>>,
+    pending_logins: Mutex<Vec<PendingLogin>>,
+    // One-time token for creating the first admin, until one exists
+    setup_token: Mutex<Option<String>>,
 }
 
 // Loads courses from file or initializes if not found
@@ -122,49 +319,68 @@ fn save_courses(courses: &Vec<Course>) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-// Loads the schedule from file
-fn load_schedule() -> Schedule {
-    fs::read_to_string("schedule.json")
+// Contents of schedule.json, either one schedule per student or the older single schedule
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScheduleFile {
+    Single(Schedule),
+    PerUser(HashMap<String, Schedule>),
+}
+
+// Loads every student's schedule from file
+fn load_schedules(users: &[User]) -> HashMap<String, Schedule> {
+    let file = fs::read_to_string("schedule.json")
         .ok()
-        .and_then(|data| serde_json::from_str(&data).ok())
-        .unwrap_or_default()
+        .and_then(|data| serde_json::from_str(&data).ok());
+    
+    match file {
+        Some(ScheduleFile::PerUser(schedules)) => schedules,
+        // Older files held a single schedule, hand it to the first student
+        Some(ScheduleFile::Single(schedule)) => users
+            .iter()
+            .find(|u| u.role == Role::Student)
+            .map(|u| HashMap::from([(u.username.clone(), schedule)]))
+            .unwrap_or_default(),
+        None => HashMap::new(),
+    }
 }
 
-// Saves the current schedule
-fn save_schedule(schedule: &Schedule) -> Result<(), std::io::Error> {
-    let data = serde_json::to_string_pretty(schedule)?;
+// Saves every student's schedule
+fn save_schedules(schedules: &HashMap<String, Schedule>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(schedules)?;
     fs::write("schedule.json", data)?;
     
     Ok(())
 }
 
-// Loads users from file or initializes defaults
-fn load_users() -> Vec<User> {
-    if let Ok(data) = fs::read_to_string("users.json") {
-        serde_json::from_str(&data).unwrap_or_else(|e| {
-            println!("Failed to parse users.json: {:?}", e);
-            initialize_users()
-        })
-    } else {
-        println!("No users.json found, initializing with defaults");
-        initialize_users()
-    }
+// Loads override requests from file
+fn load_overrides() -> Vec<OverrideRequest> {
+    fs::read_to_string("overrides.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves override requests
+fn save_overrides(overrides: &Vec<OverrideRequest>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(overrides)?;
+    fs::write("overrides.json", data)?;
+    Ok(())
 }
 
-// Sets up default users (admin account)
-fn initialize_users() -> Vec<User> {
-    let initial_users = vec![
-        User {
-            username: "admin".to_string(),
-            password: "admin".to_string(),
-            role: "admin".to_string(),
+// Loads users from file. Without any, the first admin is created through first-run setup.
+// A file that can't be read is an error rather than no users, since setup would then overwrite it.
+fn load_users() -> std::io::Result<Vec<User>> {
+    match fs::read_to_string("users.json") {
+        Ok(data) => serde_json::from_str(&data).map_err(|e| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("Failed to parse users.json: {}", e))
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            println!("No users.json found, starting without users");
+            Ok(Vec::new())
         }
-    ];
-    
-    if let Err(e) = save_users(&initial_users) {
-        println!("Failed to initialize users.json: {:?}", e);
+        Err(e) => Err(e),
     }
-    initial_users
 }
 
 // Saves user list to file
@@ -174,13 +390,6 @@ fn save_users(users: &Vec<User>) -> Result<(), std::io::Error> {
     Ok(())
 }
 
-// Loads current session info
-fn load_session() -> Option<Session> {
-    fs::read_to_string("session.json")
-        .ok()
-        .and_then(|data| serde_json::from_str(&data).ok())
-}
-
 // Saves or clears session data
 fn save_session(session: &Option<Session>) -> Result<(), std::io::Error> {
     if let Some(session) = session {
@@ -194,480 +403,1103 @@ fn save_session(session: &Option<Session>) -> Result<(), std::io::Error> {
 }
 
 // Handles user login
-async fn login(login: web::Json<LoginRequest>, state: web::Data<AppState>) -> impl Responder {
+#[utoipa::path(
+    post,
+    path = "/api/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Logged in, or the password was accepted and a two-factor code is needed next", body = LoginResponse),
+        (status = 401, description = "Invalid credentials", body = ApiErrorBody),
+        (status = 403, description = "Another user is already logged in", body = ApiErrorBody),
+        (status = 429, description = "Too many failed logins for the account or address, see Retry-After", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+async fn login(req: HttpRequest, login: web::Json<LoginRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let address = req.peer_addr().map(|a| a.ip());
+    check_login_allowed(&state, &login.username, address)?;
+    
     let users = state.users.lock().unwrap();
     let mut session = state.session.lock().unwrap();
     
     if session.is_some() {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Another user is already logged in".to_string() 
-        });
+        return Err(ApiError::SessionActive);
     }
     
-    if let Some(user) = users.iter().find(|u| u.username == login.username && u.password == login.password) {
-        *session = Some(Session { username: user.username.clone() });
-        
-        if let Err(e) = save_session(&*session) {
-            return HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save session: {}", e) 
-            });
-        }
-        HttpResponse::Ok().json(LoginResponse { role: user.role.clone() })
-    } else {
-        HttpResponse::Unauthorized().json(ErrorResponse { 
-            error: "Invalid credentials".to_string() 
-        })
+    let Some(user) = users
+        .iter()
+        .find(|u| u.username == login.username && u.password == login.password && u.activation.is_none())
+    else {
+        let account_exists = users.iter().any(|u| u.username == login.username);
+        drop(session);
+        drop(users);
+        let locked = record_login_failure(&state, &login.username, account_exists, address);
+        return Err(locked.unwrap_or(ApiError::InvalidCredentials));
+    };
+    
+    // Failures are only forgotten once the second step succeeds too, so codes can't be guessed between correct passwords
+    if two_factor_enabled(&state, &user.username) {
+        return Ok(HttpResponse::Ok().json(LoginResponse {
+            role: user.role,
+            must_change_password: false,
+            two_factor_challenge: Some(start_two_factor_login(&state, &user.username)),
+            two_factor_setup_required: false,
+        }));
     }
+    record_login_success(&state, &user.username);
+    
+    *session = Some(Session { username: user.username.clone() });
+    save_session(&session).map_err(|e| ApiError::storage("session", e))?;
+    Ok(HttpResponse::Ok().json(LoginResponse {
+        role: user.role,
+        must_change_password: user.must_change_password,
+        two_factor_challenge: None,
+        two_factor_setup_required: user.role == Role::Admin,
+    }))
 }
 
 // Logs out the current user
-async fn logout(state: web::Data<AppState>) -> impl Responder {
+#[utoipa::path(
+    post,
+    path = "/api/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Logged out"),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+async fn logout(state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
     let mut session = state.session.lock().unwrap();
     *session = None;
     
-    match save_session(&*session) {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Logged out"})),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-            error: format!("Failed to clear session: {}", e) 
-        })
-    }
+    save_session(&session).map_err(|e| ApiError::storage("session", e))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Logged out"})))
 }
 
-// Adds a new student user (admin only)
-async fn add_user(user: web::Json<User>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can add users".to_string() 
-        });
+// Parses "HH:MM" into minutes since midnight
+fn parse_clock(time: &str) -> Option<u32> {
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    if hour >= 24 || minute >= 60 {
+        return None;
     }
-    
-    let mut users = state.users.lock().unwrap();
-    if users.iter().any(|u| u.username == user.username) {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Username already exists".to_string() 
-        });
+    Some(hour * 60 + minute)
+}
+
+// Earliest start and latest end of a class allowed by `validate_course`, in minutes since midnight
+const DAY_START: u32 = 8 * 60;
+const DAY_END: u32 = 21 * 60;
+
+// Checks the department code and meeting time format of a course
+fn validate_course(course: &NewCourse) -> Result<(), ApiError> {
+    if course.dept_code != "CS" {
+        return Err(ApiError::validation("dept_code", "Department code must be 'CS'"));
     }
     
-    let new_user = User {
-        username: user.username.clone(),
-        password: user.password.clone(),
-        role: "student".to_string(),
-    };
-    
-    users.push(new_user);
-    match save_users(&users) {
-        Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Student added"})),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-            error: format!("Failed to save users: {}", e) 
-        }),
+    for slot in course.meeting_time.split(", ") {
+        let parts: Vec<&str> = slot.split(' ').collect();
+        if parts.len() != 2 {
+            return Err(ApiError::validation("meeting_time", "Invalid meeting time format (e.g., 'M 08:00-15:00')"));
+        }
+        
+        let times: Vec<&str> = parts[1].split('-').collect();
+        if times.len() != 2 {
+            return Err(ApiError::validation("meeting_time", "Invalid time range"));
+        }
+        
+        let (Some(start), Some(end)) = (parse_clock(times[0]), parse_clock(times[1])) else {
+            return Err(ApiError::validation("meeting_time", "Invalid time range"));
+        };
+        
+        if start < DAY_START || end > DAY_END {
+            return Err(ApiError::validation(
+                "meeting_time",
+                &format!("Classes must be between {} and {}", format_minutes(DAY_START), format_minutes(DAY_END)),
+            ));
+        }
+        
+        if start >= end {
+            return Err(ApiError::validation("meeting_time", "End time must be after start time"));
+        }
     }
+    Ok(())
 }
 
-// Deletes a student user (admin only)
-async fn delete_user(username: web::Json<String>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can delete users".to_string() 
-        });
+// Builds a stored course from submitted course data
+fn build_course(id: u32, course: NewCourse) -> Course {
+    Course {
+        id,
+        dept_code: course.dept_code,
+        course_number: course.course_number,
+        instructor: course.instructor,
+        description: course.description,
+        location: course.location,
+        meeting_time: course.meeting_time,
     }
-    
-    let mut users = state.users.lock().unwrap();
-    let username = username.into_inner();
-    
-    if username == "admin" {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Cannot delete admin account".to_string() 
-        });
+}
+
+// Saves courses and the schedules after a catalog change
+fn persist_catalog(courses: &Vec<Course>, schedules: &HashMap<String, Schedule>) -> Result<(), ApiError> {
+    save_courses(courses).map_err(|e| ApiError::storage("courses", e))?;
+    save_schedules(schedules).map_err(|e| ApiError::storage("schedule", e))
+}
+
+// Works out which students a change to a course affects; `replacement` is None for a deletion.
+// Only new clashes count: a student who already clashes with a course on a day,
+// e.g. under a granted time conflict override, isn't dropped for clashing with it again.
+fn course_impact(courses: &[Course], schedules: &HashMap<String, Schedule>, course_id: u32, replacement: Option<&Course>) -> CourseImpact {
+    let current = courses.iter().find(|c| c.id == course_id);
+    let mut affected_students = Vec::new();
+    let mut conflicting_students = Vec::new();
+    for (username, schedule) in schedules {
+        if !schedule.courses.iter().any(|sc| sc.course_id == course_id) {
+            continue;
+        }
+        affected_students.push(username.clone());
+        if let Some(course) = replacement {
+            let existing = current.map(|c| schedule_conflicts(c, schedule, courses)).unwrap_or_default();
+            let conflicts: Vec<ConflictDetail> = schedule_conflicts(course, schedule, courses)
+                .into_iter()
+                .filter(|c| !existing.iter().any(|e| e.course_id == c.course_id && e.day == c.day))
+                .collect();
+            if !conflicts.is_empty() {
+                conflicting_students.push(StudentConflicts { username: username.clone(), conflicts });
+            }
+        }
     }
     
-    if let Some(index) = users.iter().position(|u| u.username == username) {
-        users.remove(index);
-        match save_users(&users) {
-            Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Student deleted"})),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save users: {}", e) 
-            }),
+    affected_students.sort();
+    conflicting_students.sort_by(|a, b| a.username.cmp(&b.username));
+    CourseImpact { affected_students, conflicting_students }
+}
+
+// Removes a course from the given students' schedules, returning the removed entries
+fn unschedule_students(schedules: &mut HashMap<String, Schedule>, course_id: u32, usernames: &[String]) -> Vec<(String, ScheduledCourse)> {
+    let mut removed = Vec::new();
+    for (username, schedule) in schedules.iter_mut().filter(|(username, _)| usernames.contains(username)) {
+        if let Some(index) = schedule.courses.iter().position(|sc| sc.course_id == course_id) {
+            removed.push((username.clone(), schedule.courses.remove(index)));
+            schedule.mark_changed();
         }
-    } else {
-        HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "User not found".to_string() 
-        })
     }
+    removed
 }
 
-// Returns list of student users (admin only)
-async fn get_users(state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can view users".to_string() 
-        });
+// Validates and stores a new course
+fn create_course(state: &AppState, actor: &str, course: NewCourse) -> Result<Course, ApiError> {
+    validate_course(&course)?;
+    let mut created = create_courses(state, actor, vec![course])?;
+    Ok(created.remove(0))
+}
+
+// Stores already validated courses with consecutive IDs, writing the catalog once
+fn create_courses(state: &AppState, actor: &str, new_courses: Vec<NewCourse>) -> Result<Vec<Course>, ApiError> {
+    let mut courses = state.courses.lock().unwrap();
+    add_courses(state, actor, &mut courses, new_courses)
+}
+
+// Adds courses to the already locked catalog, so callers can check it and add under one lock
+fn add_courses(state: &AppState, actor: &str, courses: &mut Vec<Course>, new_courses: Vec<NewCourse>) -> Result<Vec<Course>, ApiError> {
+    let first_id = courses.iter().map(|c| c.id).max().unwrap_or(0).max(highest_course_id(state)) + 1;
+    let created: Vec<Course> = (first_id..).zip(new_courses).map(|(id, course)| build_course(id, course)).collect();
+    courses.extend(created.iter().cloned());
+    save_courses(courses).map_err(|e| ApiError::storage("courses", e))?;
+    for new_course in &created {
+        record_version(state, actor, VersionChange::Created, None, new_course, Vec::new())?;
+        audit::record(state, actor, "course.create", format!("course:{}", new_course.id), None::<&Course>, Some(new_course));
+        publish(state, Event::CourseAdded { course: new_course.clone() });
     }
-    
-    let users = state.users.lock().unwrap();
-    let student_users: Vec<&User> = users.iter().filter(|u| u.role == "student").collect();
-    HttpResponse::Ok().json(student_users)
+    Ok(created)
 }
 
-// Adds a new course (admin only)
-async fn add_course(course: web::Json<NewCourse>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can add courses".to_string() 
-        });
+// Reports who a change to a course would affect without making it; `course` is None for a deletion
+fn preview_course_change(state: &AppState, id: u32, course: Option<NewCourse>) -> Result<CourseImpact, ApiError> {
+    if let Some(course) = &course {
+        validate_course(course)?;
+    }
+    
+    let courses = state.courses.lock().unwrap();
+    let schedules = state.schedules.lock().unwrap();
+    if !courses.iter().any(|c| c.id == id) {
+        return Err(ApiError::CourseNotFound);
     }
     
+    let replacement = course.map(|c| build_course(id, c));
+    Ok(course_impact(&courses, &schedules, id, replacement.as_ref()))
+}
+
+// Replaces a course and removes it from schedules, or only from those it would now conflict with
+fn replace_course(state: &AppState, actor: &str, id: u32, course: NewCourse, keep_enrolled: bool) -> Result<Course, ApiError> {
+    validate_course(&course)?;
+    
     let mut courses = state.courses.lock().unwrap();
-    let new_id = courses.len() as u32 + 1;
-    
-    let new_course = Course {
-        id: new_id,
-        dept_code: course.dept_code.clone(),
-        course_number: course.course_number.clone(),
-        instructor: course.instructor.clone(),
-        description: course.description.clone(),
-        location: course.location.clone(),
-        meeting_time: course.meeting_time.clone(),
+    let mut schedules = state.schedules.lock().unwrap();
+    let index = courses
+        .iter()
+        .position(|c| c.id == id)
+        .ok_or(ApiError::CourseNotFound)?;
+    
+    let replacement = build_course(id, course);
+    let impact = course_impact(&courses, &schedules, id, Some(&replacement));
+    let dropped: Vec<String> = if keep_enrolled {
+        impact.conflicting_students.into_iter().map(|s| s.username).collect()
+    } else {
+        impact.affected_students.clone()
     };
+    let kept: Vec<String> = impact.affected_students.into_iter().filter(|s| !dropped.contains(s)).collect();
     
-    if new_course.dept_code != "CS" {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Department code must be 'CS'".to_string() 
-        });
+    let before = std::mem::replace(&mut courses[index], replacement);
+    let removed = unschedule_students(&mut schedules, id, &dropped);
+    persist_catalog(&courses, &schedules)?;
+    record_version(state, actor, VersionChange::Updated, Some(&before), &courses[index], removed)?;
+    audit::record(state, actor, "course.update", format!("course:{}", id), Some(&before), Some(&courses[index]));
+
+    publish(state, Event::CourseUpdated { course: courses[index].clone() });
+    for username in &dropped {
+        schedule_changed(state, &schedules, username);
     }
+    enrollment_changed(state, &schedules, id);
     
-    let slots = new_course.meeting_time.split(", ");
-    for slot in slots {
-        let parts: Vec<&str> = slot.split(' ').collect();
-        if parts.len() != 2 {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Invalid meeting time format (e.g., 'M 08:00-15:00')".to_string() 
-            });
+    let label = course_label(&courses[index]);
+    notify(state, &dropped, NotificationKind::CourseDropped, Some(id), &format!("{} was changed and removed from your schedule", label));
+    notify(state, &kept, NotificationKind::CourseUpdated, Some(id), &format!("{} in your schedule was changed", label));
+    Ok(courses[index].clone())
+}
+
+// Deletes a course and removes it from every schedule
+fn remove_course(state: &AppState, actor: &str, id: u32) -> Result<(), ApiError> {
+    let mut courses = state.courses.lock().unwrap();
+    let mut schedules = state.schedules.lock().unwrap();
+    let index = courses
+        .iter()
+        .position(|c| c.id == id)
+        .ok_or(ApiError::CourseNotFound)?;
+    
+    let impact = course_impact(&courses, &schedules, id, None);
+    let before = courses.remove(index);
+    let removed = unschedule_students(&mut schedules, id, &impact.affected_students);
+    persist_catalog(&courses, &schedules)?;
+    record_version(state, actor, VersionChange::Deleted, Some(&before), &before, removed)?;
+    audit::record(state, actor, "course.delete", format!("course:{}", id), Some(&before), None::<&Course>);
+    publish(state, Event::CourseDeleted { course_id: id });
+    for username in &impact.affected_students {
+        schedule_changed(state, &schedules, username);
+    }
+    notify(
+        state,
+        &impact.affected_students,
+        NotificationKind::CourseDeleted,
+        Some(id),
+        &format!("{} was removed from the catalog and dropped from your schedule", course_label(&before)),
+    );
+    Ok(())
+}
+
+// Creates a student account, or replaces the password of an existing non-admin account
+fn upsert_student(state: &AppState, actor: &str, username: String, password: String, allow_replace: bool) -> Result<(User, bool), ApiError> {
+    let mut users = state.users.lock().unwrap();
+    let before = users.iter().find(|u| u.username == username).cloned();
+    let created = match users.iter_mut().find(|u| u.username == username) {
+        Some(_) if !allow_replace => {
+            return Err(ApiError::UsernameTaken);
         }
-        
-        let times: Vec<&str> = parts[1].split('-').collect();
-        if times.len() != 2 {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Invalid time range".to_string() 
-            });
+        Some(user) if user.role == Role::Admin => {
+            return Err(ApiError::ProtectedAccount("Cannot modify admin account".to_string()));
         }
-        
-        let start: Vec<u32> = times[0].split(':').map(|x| x.parse().unwrap()).collect();
-        let end: Vec<u32> = times[1].split(':').map(|x| x.parse().unwrap()).collect();
-        let start_hour = start[0];
-        let end_hour = end[0];
-        
-        if start_hour < 8 || end_hour > 21 || (end_hour == 21 && end[1] > 0) {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Classes must be between 08:00 and 21:00".to_string() 
-            });
+        Some(user) => {
+            validate_password_strength("password", &username, &password)?;
+            user.password = password;
+            // A password set by the admin replaces any pending activation and is only good until first login
+            user.activation = None;
+            user.must_change_password = true;
+            false
         }
-        
-        if start_hour > end_hour || (start_hour == end_hour && start[1] >= end[1]) {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "End time must be after start time".to_string() 
+        None => {
+            validate_password_strength("password", &username, &password)?;
+            users.push(User {
+                username: username.clone(),
+                password,
+                role: Role::Student,
+                instructor_name: None,
+                advisor: None,
+                email: None,
+                email_notifications: Vec::new(),
+                // Like any password the admin sets, it is only good until first login
+                must_change_password: true,
+                activation: None,
             });
+            true
         }
-    }
+    };
     
-    courses.push(new_course.clone());
-    match save_courses(&courses) {
-        Ok(_) => HttpResponse::Ok().json(new_course),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-            error: format!("Failed to save courses: {}", e) 
-        }),
-    }
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    let user = users.iter().find(|u| u.username == username).unwrap().clone();
+    let action = if created { "user.create" } else { "user.update" };
+    audit::record(state, actor, action, format!("user:{}", user.username), before.as_ref(), Some(&user));
+    Ok((user, created))
 }
 
-// Updates an existing course (admin only)
-async fn update_course(course: web::Json<UpdateCourse>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can update courses".to_string() 
-        });
+// Creates a student, instructor or advisor account
+fn create_account(state: &AppState, actor: &str, user: User) -> Result<User, ApiError> {
+    let instructor_name = match user.role {
+        Role::Admin => {
+            return Err(ApiError::ProtectedAccount("Cannot create admin accounts".to_string()));
+        }
+        Role::Student | Role::Advisor => None,
+        Role::Instructor => match user.instructor_name {
+            Some(name) if !name.trim().is_empty() => Some(name),
+            _ => return Err(ApiError::validation("instructor_name", "Instructors need the name shown on their courses")),
+        },
+    };
+    
+    if let Some(email) = &user.email {
+        validate_email(email)?;
     }
+    validate_password_strength("password", &user.username, &user.password)?;
     
-    let mut courses = state.courses.lock().unwrap();
-    let mut schedule = state.schedule.lock().unwrap();
-    let course_data = course.into_inner();
+    let mut users = state.users.lock().unwrap();
+    if users.iter().any(|u| u.username == user.username) {
+        return Err(ApiError::UsernameTaken);
+    }
+    
+    // Advisors are assigned separately once both accounts exist, email notifications are the user's choice,
+    // and the password set by the admin has to be replaced at first login
+    let user = User {
+        instructor_name,
+        advisor: None,
+        email_notifications: Vec::new(),
+        must_change_password: true,
+        activation: None,
+        ..user
+    };
+    users.push(user.clone());
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    audit::record(state, actor, "user.create", format!("user:{}", user.username), None::<&User>, Some(&user));
+    Ok(user)
+}
+
+// Lists every account except admins
+fn list_accounts(state: &AppState) -> Vec<User> {
+    let users = state.users.lock().unwrap();
+    users.iter().filter(|u| u.role != Role::Admin).cloned().collect()
+}
+
+// Deletes a non-admin account along with its schedule and advisor assignments
+fn remove_user(state: &AppState, actor: &str, username: &str) -> Result<(), ApiError> {
+    let mut users = state.users.lock().unwrap();
+    let index = users
+        .iter()
+        .position(|u| u.username == username)
+        .ok_or(ApiError::UserNotFound)?;
     
-    //dbg, didn't get to finish doing non c
-    if course_data.dept_code != "CS" {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Department code must be 'CS'".to_string() 
-        });
+    if users[index].role == Role::Admin {
+        return Err(ApiError::ProtectedAccount("Cannot delete admin account".to_string()));
     }
     
-    let slots = course_data.meeting_time.split(", ");
-    for slot in slots {
-        let parts: Vec<&str> = slot.split(' ').collect();
-        if parts.len() != 2 {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Invalid meeting time format (e.g., 'M 08:00-15:00')".to_string() 
-            });
-        }
-        
-        let times: Vec<&str> = parts[1].split('-').collect();
-        if times.len() != 2 {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Invalid time range".to_string() 
-            });
-        }
-        
-        let start: Vec<u32> = times[0].split(':').map(|x| x.parse().unwrap()).collect();
-        let end: Vec<u32> = times[1].split(':').map(|x| x.parse().unwrap()).collect();
-        let start_hour = start[0];
-        let end_hour = end[0];
-        
-        if start_hour < 8 || end_hour > 21 || (end_hour == 21 && end[1] > 0) {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "Classes must be between 08:00 and 21:00".to_string() 
-            });
-        }
-        
-        if start_hour > end_hour || (start_hour == end_hour && start[1] >= end[1]) {
-            return HttpResponse::BadRequest().json(ErrorResponse { 
-                error: "End time must be after start time".to_string() 
-            });
-        }
+    let before = users.remove(index);
+    for user in users.iter_mut().filter(|u| u.advisor.as_deref() == Some(username)) {
+        user.advisor = None;
     }
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    drop(users);
     
-    if let Some(index) = courses.iter().position(|c| c.id == course_data.id) {
-        courses[index] = Course {
-            id: course_data.id,
-            dept_code: course_data.dept_code,
-            course_number: course_data.course_number,
-            instructor: course_data.instructor,
-            description: course_data.description,
-            location: course_data.location,
-            meeting_time: course_data.meeting_time,
-        };
-        
-        schedule.courses.retain(|sc| sc.course_id != course_data.id);
-        match save_courses(&courses) {
-            Ok(_) => match save_schedule(&schedule) {
-                Ok(_) => HttpResponse::Ok().json(&courses[index]),
-                Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-                    error: format!("Failed to save schedule: {}", e) 
-                }),
-            },
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save courses: {}", e) 
-            }),
+    let mut schedules = state.schedules.lock().unwrap();
+    if let Some(schedule) = schedules.remove(username) {
+        save_schedules(&schedules).map_err(|e| ApiError::storage("schedule", e))?;
+        for entry in &schedule.courses {
+            enrollment_changed(state, &schedules, entry.course_id);
         }
-    } else {
-        HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Course not found".to_string() 
-        })
     }
+    drop(schedules);
+    clear_notifications(state, username)?;
+    forget_user(state, username)?;
+    revoke_share_links(state, username)?;
+    revoke_password_resets(state, username)?;
+    remove_two_factor(state, username)?;
+    audit::record(state, actor, "user.delete", format!("user:{}", username), Some(&before), None::<&User>);
+    Ok(())
 }
 
-// Deletes a course (admin only)
-async fn delete_course(course_id: web::Json<u32>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username != "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only admin can delete courses".to_string() 
-        });
-    }
+// Adds a course to a student's schedule, returning the schedule and whether it was newly added
+fn schedule_course(state: &AppState, username: &str, course_id: u32) -> Result<(Schedule, bool), ApiError> {
+    let allow_conflicts = has_granted_override(state, username, course_id, OverrideRule::TimeConflict);
+    let courses = state.courses.lock().unwrap();
+    let course = courses
+        .iter()
+        .find(|c| c.id == course_id)
+        .ok_or(ApiError::CourseNotFound)?;
+    let mut schedules = state.schedules.lock().unwrap();
+    let schedule = schedules.entry(username.to_string()).or_default();
     
-    let mut courses = state.courses.lock().unwrap();
-    let mut schedule = state.schedule.lock().unwrap();
-    let course_id = course_id.into_inner();
+    let conflicts = schedule_conflicts(course, schedule, &courses);
+    if !conflicts.is_empty() && !allow_conflicts {
+        return Err(ApiError::TimeConflict(ConflictDetails {
+            conflicts,
+            alternatives: alternative_sections(course, schedule, &courses),
+        }));
+    }
     
-    if let Some(index) = courses.iter().position(|c| c.id == course_id) {
-        courses.remove(index);
-        schedule.courses.retain(|sc| sc.course_id != course_id);
-        
-        match save_courses(&courses) {
-            Ok(_) => match save_schedule(&schedule) {
-                Ok(_) => HttpResponse::Ok().json(serde_json::json!({"message": "Course deleted"})),
-                Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-                    error: format!("Failed to save schedule: {}", e) 
-                }),
-            },
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save courses: {}", e) 
-            }),
-        }
-    } else {
-        HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Course not found".to_string() 
-        })
+    if schedule.courses.iter().any(|c| c.course_id == course_id) {
+        return Ok((schedule.clone(), false));
     }
+    
+    schedule.courses.push(ScheduledCourse {
+        course_id,
+        notes: String::new(),
+        slot: String::new(),
+    });
+    schedule.mark_changed();
+    let schedule = schedule.clone();
+    save_schedules(&schedules).map_err(|e| ApiError::storage("schedule", e))?;
+    audit::record(state, username, "schedule.add", format!("schedule:{}", username), None::<&ScheduledCourse>, schedule.courses.last());
+    schedule_changed(state, &schedules, username);
+    enrollment_changed(state, &schedules, course_id);
+    Ok((schedule, true))
+}
+
+// Returns a copy of a student's schedule, empty if they have none yet
+fn schedule_of(state: &AppState, username: &str) -> Schedule {
+    state.schedules.lock().unwrap().get(username).cloned().unwrap_or_default()
+}
+
+// Replaces the notes and slot of a course in a student's schedule
+fn edit_schedule_entry(state: &AppState, username: &str, entry: ScheduledCourse) -> Result<ScheduledCourse, ApiError> {
+    let mut schedules = state.schedules.lock().unwrap();
+    let schedule = schedules.get_mut(username).ok_or(ApiError::NotInSchedule)?;
+    let index = schedule
+        .courses
+        .iter()
+        .position(|c| c.course_id == entry.course_id)
+        .ok_or(ApiError::NotInSchedule)?;
+    
+    let before = std::mem::replace(&mut schedule.courses[index], entry);
+    let entry = schedule.courses[index].clone();
+    save_schedules(&schedules).map_err(|e| ApiError::storage("schedule", e))?;
+    audit::record(state, username, "schedule.update", format!("schedule:{}", username), Some(&before), Some(&entry));
+    schedule_changed(state, &schedules, username);
+    Ok(entry)
+}
+
+// Removes a course from a student's schedule, returning the schedule and whether it was there
+fn unschedule_course(state: &AppState, username: &str, course_id: u32) -> Result<(Schedule, bool), ApiError> {
+    let mut schedules = state.schedules.lock().unwrap();
+    let Some(schedule) = schedules.get_mut(username) else {
+        return Ok((Schedule::default(), false));
+    };
+    let Some(index) = schedule.courses.iter().position(|c| c.course_id == course_id) else {
+        return Ok((schedule.clone(), false));
+    };
+    
+    let before = schedule.courses.remove(index);
+    schedule.mark_changed();
+    let schedule = schedule.clone();
+    save_schedules(&schedules).map_err(|e| ApiError::storage("schedule", e))?;
+    audit::record(state, username, "schedule.drop", format!("schedule:{}", username), Some(&before), None::<&ScheduledCourse>);
+    schedule_changed(state, &schedules, username);
+    enrollment_changed(state, &schedules, course_id);
+    Ok((schedule, true))
+}
+
+// Pairs each course in a student's schedule with its catalog entry
+fn scheduled_courses(state: &AppState, username: &str) -> Vec<(ScheduledCourse, Course)> {
+    let schedule = schedule_of(state, username);
+    let courses = state.courses.lock().unwrap();
+    
+    schedule
+        .courses
+        .iter()
+        .filter_map(|sc| courses.iter().find(|c| c.id == sc.course_id).map(|c| (sc.clone(), c.clone())))
+        .collect()
+}
+
+// Returns the logged-in user
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    tag = "auth",
+    responses(
+        (status = 200, description = "The logged-in user", body = AuthUser),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+    )
+)]
+async fn whoami(user: AuthUser) -> impl Responder {
+    HttpResponse::Ok().json(user)
+}
+
+// Adds a new student user (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/add_user",
+    tag = "legacy",
+    request_body = User,
+    responses(
+        (status = 200, description = "Student added"),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use POST /api/users"]
+async fn add_user(admin: AuthUser, user: web::Json<User>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let user = user.into_inner();
+    upsert_student(&state, &admin.username, user.username, user.password, false)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Student added"})))
+}
+
+// Deletes a student user (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/delete_user",
+    tag = "legacy",
+    request_body = String,
+    responses(
+        (status = 200, description = "Student deleted"),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use DELETE /api/users/{username}"]
+async fn delete_user(admin: AuthUser, username: web::Json<String>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    remove_user(&state, &admin.username, &username.into_inner())?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Student deleted"})))
+}
+
+// Returns list of student users (admin only)
+#[utoipa::path(
+    get,
+    path = "/api/admin/get_users",
+    tag = "legacy",
+    responses(
+        (status = 200, description = "Student and instructor accounts", body = Vec<User>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use GET /api/users"]
+async fn get_users(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(list_accounts(&state))
+}
+
+// Adds a new course (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/add_course",
+    tag = "legacy",
+    request_body = NewCourse,
+    responses(
+        (status = 200, description = "Course added", body = Course),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use POST /api/courses"]
+async fn add_course(admin: AuthUser, course: web::Json<NewCourse>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let course = create_course(&state, &admin.username, course.into_inner())?;
+    Ok(HttpResponse::Ok().json(course))
+}
+
+// Updates an existing course (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/update_course",
+    tag = "legacy",
+    request_body = UpdateCourse,
+    responses(
+        (status = 200, description = "Course updated", body = Course),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use PUT /api/courses/{id}"]
+async fn update_course(admin: AuthUser, course: web::Json<UpdateCourse>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let course = course.into_inner();
+    let id = course.id;
+    let data = NewCourse {
+        dept_code: course.dept_code,
+        course_number: course.course_number,
+        instructor: course.instructor,
+        description: course.description,
+        location: course.location,
+        meeting_time: course.meeting_time,
+    };
+    
+    let course = replace_course(&state, &admin.username, id, data, false)?;
+    Ok(HttpResponse::Ok().json(course))
+}
+
+// Deletes a course (admin only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/delete_course",
+    tag = "legacy",
+    request_body = u32,
+    responses(
+        (status = 200, description = "Course deleted"),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use DELETE /api/courses/{id}"]
+async fn delete_course(admin: AuthUser, course_id: web::Json<u32>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    remove_course(&state, &admin.username, course_id.into_inner())?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Course deleted"})))
 }
 
 // Returns all available courses
+#[utoipa::path(
+    get,
+    path = "/api/courses",
+    tag = "courses",
+    responses(
+        (status = 200, description = "All courses", body = Vec<Course>),
+    )
+)]
 async fn get_courses(state: web::Data<AppState>) -> impl Responder {
     let courses = state.courses.lock().unwrap();
     HttpResponse::Ok().json(&*courses)
 }
 
 // Adds a course to the student’s schedule
-async fn add_to_schedule(course_id: web::Json<u32>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username == "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only students can modify schedule".to_string() 
-        });
+#[utoipa::path(
+    post,
+    path = "/api/student/add_to_schedule",
+    tag = "legacy",
+    request_body = u32,
+    responses(
+        (status = 200, description = "Updated schedule", body = Schedule),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 409, description = "Time conflict with a scheduled course", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use PUT /api/me/schedule/{course_id}"]
+async fn add_to_schedule(user: AuthUser, course_id: web::Json<u32>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let (schedule, _) = schedule_course(&state, &user.username, course_id.into_inner())?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+// Updates a schedule entry
+#[utoipa::path(
+    post,
+    path = "/api/student/update_schedule_entry",
+    tag = "legacy",
+    request_body = ScheduledCourse,
+    responses(
+        (status = 200, description = "Updated entry", body = ScheduledCourse),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use PATCH /api/me/schedule/{course_id}"]
+async fn update_schedule_entry(user: AuthUser, entry: web::Json<ScheduledCourse>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let entry = edit_schedule_entry(&state, &user.username, entry.into_inner())?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+// Removes a course from the schedule
+#[utoipa::path(
+    post,
+    path = "/api/student/drop_from_schedule",
+    tag = "legacy",
+    request_body = u32,
+    responses(
+        (status = 200, description = "Updated schedule", body = Schedule),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use DELETE /api/me/schedule/{course_id}"]
+async fn drop_from_schedule(user: AuthUser, course_id: web::Json<u32>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let course_id = course_id.into_inner();
+    if !state.courses.lock().unwrap().iter().any(|c| c.id == course_id) {
+        return Err(ApiError::CourseNotFound.into());
     }
     
+    let (schedule, _) = unschedule_course(&state, &user.username, course_id)?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+// Swaps one scheduled course for another, keeping the old one if the new one can't be added
+fn swap_scheduled_course(state: &AppState, username: &str, swap: SwapRequest) -> Result<Schedule, ApiError> {
+    let allow_conflicts = has_granted_override(state, username, swap.add_course_id, OverrideRule::TimeConflict);
     let courses = state.courses.lock().unwrap();
-    let course_id = course_id.into_inner();
-    let course = courses.iter().find(|c| c.id == course_id);
+    let mut schedules = state.schedules.lock().unwrap();
+    let schedule = schedules.get(username).ok_or(ApiError::NotInSchedule)?;
     
-    if course.is_none() {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Course not found".to_string() 
-        });
+    if !schedule.courses.iter().any(|c| c.course_id == swap.drop_course_id) {
+        return Err(ApiError::NotInSchedule);
     }
     
-    let course = course.unwrap();
-    let mut schedule = state.schedule.lock().unwrap();
+    let course = courses
+        .iter()
+        .find(|c| c.id == swap.add_course_id)
+        .ok_or(ApiError::CourseNotFound)?;
     
-    for scheduled in &schedule.courses {
-        let existing_course = courses.iter().find(|c| c.id == scheduled.course_id).unwrap();
-        if has_time_conflict(&course.meeting_time, &existing_course.meeting_time) {
-            return HttpResponse::Conflict().json(ErrorResponse { 
-                error: "Time conflict with existing course".to_string() 
-            });
-        }
+    if schedule.courses.iter().any(|c| c.course_id == swap.add_course_id) {
+        return Err(ApiError::AlreadyInSchedule);
     }
     
-    if !schedule.courses.iter().any(|c| c.course_id == course_id) {
-        schedule.courses.push(ScheduledCourse {
-            course_id,
-            notes: String::new(),
-            slot: String::new(),
-        });
-        
-        if let Err(e) = save_schedule(&schedule) {
-            return HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save schedule: {}", e) 
-            });
-        }
+    // Work on a copy so the original schedule stays untouched if anything fails
+    let previous = schedule.clone();
+    let mut updated = schedule.clone();
+    updated.courses.retain(|c| c.course_id != swap.drop_course_id);
+    
+    let conflicts = schedule_conflicts(course, &updated, &courses);
+    if !conflicts.is_empty() && !allow_conflicts {
+        return Err(ApiError::TimeConflict(ConflictDetails {
+            conflicts,
+            alternatives: alternative_sections(course, &updated, &courses),
+        }));
     }
     
-    HttpResponse::Ok().json(&*schedule)
+    updated.courses.push(ScheduledCourse {
+        course_id: swap.add_course_id,
+        notes: String::new(),
+        slot: String::new(),
+    });
+    updated.mark_changed();
+    schedules.insert(username.to_string(), updated.clone());
+    if let Err(e) = save_schedules(&schedules) {
+        // Put the old schedule back so memory matches the file
+        schedules.insert(username.to_string(), previous);
+        return Err(ApiError::storage("schedule", e));
+    }
+    audit::record(state, username, "schedule.swap", format!("schedule:{}", username), Some(&previous.courses), Some(&updated.courses));
+    schedule_changed(state, &schedules, username);
+    enrollment_changed(state, &schedules, swap.drop_course_id);
+    enrollment_changed(state, &schedules, swap.add_course_id);
+    Ok(updated)
+}
+
+// Swaps two courses in the schedule
+#[utoipa::path(
+    post,
+    path = "/api/student/swap_courses",
+    tag = "legacy",
+    request_body = SwapRequest,
+    responses(
+        (status = 200, description = "Updated schedule", body = Schedule),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 409, description = "Time conflict with a scheduled course", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use POST /api/me/schedule/swap"]
+async fn swap_courses(user: AuthUser, swap: web::Json<SwapRequest>, state: web::Data<AppState>) -> Result<HttpResponse, LegacyError> {
+    let schedule = swap_scheduled_course(&state, &user.username, swap.into_inner())?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
+
+// Returns the student’s current schedule
+#[utoipa::path(
+    get,
+    path = "/api/student/schedule",
+    tag = "legacy",
+    responses(
+        (status = 200, description = "Scheduled courses with their catalog entries", body = Vec<(ScheduledCourse, Course)>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+#[deprecated = "use GET /api/me/schedule"]
+async fn get_schedule(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(scheduled_courses(&state, &user.username))
 }
 
-// Updates a schedule entry (notes or slot)
-async fn update_schedule_entry(entry: web::Json<ScheduledCourse>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username == "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only students can modify schedule".to_string() 
-        });
+// A single meeting of a course on one day, in minutes since midnight
+#[derive(Debug, Clone)]
+struct MeetingSlot {
+    day: String,
+    start: u32,
+    end: u32,
+}
+
+// Details about one overlap between a requested course and a scheduled one
+#[derive(Serialize, Debug, ToSchema)]
+struct ConflictDetail {
+    course_id: u32,
+    dept_code: String,
+    course_number: String,
+    day: String,
+    overlap_start: String,
+    overlap_end: String,
+}
+
+// Why a course can't be added: the overlapping courses and sections that would fit
+#[derive(Serialize, Debug, ToSchema)]
+struct ConflictDetails {
+    conflicts: Vec<ConflictDetail>,
+    alternatives: Vec<Course>,
+}
+
+// Splits a day group like "MWF" or "TTh" into single days ("M", "W", "F")
+fn split_days(days: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut chars = days.chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut day = c.to_string();
+        if let Some(next) = chars.peek() {
+            if next.is_lowercase() {
+                day.push(*next);
+                chars.next();
+            }
+        }
+        result.push(day);
     }
-    
-    let mut schedule = state.schedule.lock().unwrap();
-    let entry = entry.into_inner();
-    
-    if let Some(index) = schedule.courses.iter().position(|c| c.course_id == entry.course_id) {
-        schedule.courses[index] = entry;
-        if let Err(e) = save_schedule(&schedule) {
-            return HttpResponse::InternalServerError().json(ErrorResponse { 
-                error: format!("Failed to save schedule: {}", e) 
-            });
+    result
+}
+
+// Parses a meeting time like "MW 10:00-11:15, F 09:00-09:50" into per-day slots
+fn parse_meeting_time(time: &str) -> Vec<MeetingSlot> {
+    let mut slots = Vec::new();
+    for slot in time.split(", ") {
+        let Some((days, range)) = slot.trim().split_once(' ') else { continue };
+        let Some((start, end)) = range.split_once('-') else { continue };
+        let (Some(start), Some(end)) = (parse_clock(start), parse_clock(end)) else { continue };
+        for day in split_days(days) {
+            slots.push(MeetingSlot { day, start, end });
         }
-        HttpResponse::Ok().json(&schedule.courses[index])
-    } else {
-        HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Course not in schedule".to_string() 
-        })
     }
+    slots
 }
 
-// Removes a course from the schedule
-async fn drop_from_schedule(course_id: web::Json<u32>, state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username == "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only students can modify schedule".to_string() 
-        });
+// Formats minutes since midnight as "HH:MM"
+fn format_minutes(minutes: u32) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+// Returns every (day, start, end) window where two meeting times overlap
+fn find_time_overlaps(time1: &str, time2: &str) -> Vec<(String, u32, u32)> {
+    let slots1 = parse_meeting_time(time1);
+    let slots2 = parse_meeting_time(time2);
+    let mut overlaps = Vec::new();
+
+    for a in &slots1 {
+        for b in &slots2 {
+            if a.day == b.day && a.start < b.end && b.start < a.end {
+                overlaps.push((a.day.clone(), a.start.max(b.start), a.end.min(b.end)));
+            }
+        }
+    }
+    overlaps
+}
+
+// Lists every scheduled course that overlaps the given course
+fn schedule_conflicts(course: &Course, schedule: &Schedule, courses: &[Course]) -> Vec<ConflictDetail> {
+    let mut conflicts = Vec::new();
+    for scheduled in &schedule.courses {
+        if scheduled.course_id == course.id {
+            continue;
+        }
+        let Some(existing) = courses.iter().find(|c| c.id == scheduled.course_id) else { continue };
+        for (day, start, end) in find_time_overlaps(&course.meeting_time, &existing.meeting_time) {
+            conflicts.push(ConflictDetail {
+                course_id: existing.id,
+                dept_code: existing.dept_code.clone(),
+                course_number: existing.course_number.clone(),
+                day,
+                overlap_start: format_minutes(start),
+                overlap_end: format_minutes(end),
+            });
+        }
     }
+    conflicts
+}
+
+// Finds other sections of the same course that fit into the schedule
+fn alternative_sections(course: &Course, schedule: &Schedule, courses: &[Course]) -> Vec<Course> {
+    courses
+        .iter()
+        .filter(|c| c.id != course.id
+            && c.dept_code == course.dept_code
+            && c.course_number == course.course_number)
+        .filter(|c| schedule_conflicts(c, schedule, courses).is_empty())
+        .cloned()
+        .collect()
+}
+
+// One API endpoint, used both to register the route and to check it is documented
+struct ApiRoute {
+    #[cfg_attr(not(test), allow(dead_code))] // only read by the OpenAPI tests
+    method: Method,
+    path: &'static str,
+    route: Route,
+    deprecated: bool,
+}
+
+// Builds an API route entry for a handler
+fn api<F, Args>(method: Method, path: &'static str, handler: F) -> ApiRoute
+where
+    F: Handler<Args>,
+    Args: FromRequest + 'static,
+    F::Output: Responder + 'static,
+{
+    ApiRoute { route: web::method(method.clone()).to(handler), method, path, deprecated: false }
+}
+
+// Builds an entry for an old RPC-style route that is kept as a deprecated alias
+fn legacy<F, Args>(method: Method, path: &'static str, handler: F) -> ApiRoute
+where
+    F: Handler<Args>,
+    Args: FromRequest + 'static,
+    F::Output: Responder + 'static,
+{
+    ApiRoute { deprecated: true, ..api(method, path, handler) }
+}
+
+// Restricts every route in the list to users with role `R`
+fn scope<R: RoleMarker>(routes: Vec<ApiRoute>) -> Vec<ApiRoute> {
+    routes
+        .into_iter()
+        .map(|entry| ApiRoute { route: entry.route.wrap(from_fn(require_role::<R>)), ..entry })
+        .collect()
+}
+
+// Every route under /api, grouped by the role needed to call it
+#[allow(deprecated)] // the legacy handlers are deprecated but still served
+fn api_routes() -> Vec<ApiRoute> {
+    let public = vec![
+        api(Method::GET, "/api/openapi.json", openapi::openapi_json),
+        api(Method::POST, "/api/login", login),
+        api(Method::POST, "/api/login/two-factor", two_factor::login_two_factor),
+        api(Method::POST, "/api/logout", logout),
+        api(Method::GET, "/api/me", whoami),
+        api(Method::POST, "/api/me/password", passwords::change_password),
+        api(Method::GET, "/api/me/two-factor", two_factor::two_factor_status),
+        api(Method::POST, "/api/me/two-factor", two_factor::start_enrollment),
+        api(Method::DELETE, "/api/me/two-factor", two_factor::disable_two_factor),
+        api(Method::POST, "/api/me/two-factor/confirm", two_factor::confirm_enrollment),
+        api(Method::POST, "/api/me/two-factor/recovery-codes", two_factor::regenerate_recovery_codes),
+        api(Method::POST, "/api/activate", provisioning::activate_account),
+        api(Method::GET, "/api/setup", setup::setup_status),
+        api(Method::POST, "/api/setup", setup::create_initial_admin),
+        api(Method::POST, "/api/password/reset", passwords::request_password_reset),
+        api(Method::POST, "/api/password/reset/confirm", passwords::confirm_password_reset),
+        api(Method::GET, "/api/events", events::event_stream),
+        api(Method::GET, "/api/me/notifications", notifications::list_notifications),
+        api(Method::POST, "/api/me/notifications/read", notifications::mark_all_read),
+        api(Method::POST, "/api/me/notifications/{id}/read", notifications::mark_read),
+        api(Method::GET, "/api/me/email", mailer::get_email_settings),
+        api(Method::PUT, "/api/me/email", mailer::put_email_settings),
+        api(Method::GET, "/api/courses", get_courses),
+        api(Method::GET, "/api/courses/export", export::export_courses),
+        api(Method::GET, "/api/courses/{id}", resources::get_course),
+        api(Method::GET, "/api/shared/{token}", share::shared_json),
+        api(Method::GET, "/api/shared/{token}/schedule.html", share::shared_html),
+        api(Method::GET, "/api/shared/{token}/schedule.ics", share::shared_ics),
+    ];
     
-    let courses = state.courses.lock().unwrap();
-    let course_id = course_id.into_inner();
+    let admin = scope::<Admin>(vec![
+        api(Method::POST, "/api/courses", resources::post_course),
+        api(Method::POST, "/api/courses/import", import::import_courses),
+        api(Method::GET, "/api/courses/enrollment/export", export::export_enrollment),
+        api(Method::PUT, "/api/courses/{id}", resources::put_course),
+        api(Method::PATCH, "/api/courses/{id}", resources::patch_course),
+        api(Method::DELETE, "/api/courses/{id}", resources::delete_course),
+        api(Method::POST, "/api/courses/{id}/undelete", history::undelete_course),
+        api(Method::GET, "/api/courses/{id}/versions", history::list_versions),
+        api(Method::GET, "/api/courses/{id}/versions/{version}", history::get_version),
+        api(Method::GET, "/api/courses/{id}/versions/{version}/diff", history::diff_version),
+        api(Method::POST, "/api/courses/{id}/versions/{version}/restore", history::restore_course_version),
+        api(Method::GET, "/api/users", resources::list_users),
+        api(Method::POST, "/api/users", resources::post_user),
+        api(Method::POST, "/api/users/import", provisioning::provision_users),
+        api(Method::GET, "/api/lockouts", throttle::list_lockouts),
+        api(Method::GET, "/api/users/{username}", resources::get_user),
+        api(Method::PUT, "/api/users/{username}", resources::put_user),
+        api(Method::PATCH, "/api/users/{username}", resources::patch_user),
+        api(Method::DELETE, "/api/users/{username}", resources::delete_user),
+        api(Method::PUT, "/api/users/{username}/advisor", approval::assign_advisor),
+        api(Method::DELETE, "/api/users/{username}/advisor", approval::unassign_advisor),
+        api(Method::POST, "/api/users/{username}/unlock", throttle::unlock_account),
+        api(Method::DELETE, "/api/users/{username}/two-factor", two_factor::reset_two_factor),
+        api(Method::GET, "/api/audit", audit::query_audit_log),
+        api(Method::GET, "/api/overrides", overrides::list_overrides),
+        api(Method::POST, "/api/overrides/{id}/grant", overrides::grant_override),
+        api(Method::POST, "/api/overrides/{id}/deny", overrides::deny_override),
+        legacy(Method::POST, "/api/admin/add_user", add_user),
+        legacy(Method::POST, "/api/admin/delete_user", delete_user),
+        legacy(Method::GET, "/api/admin/get_users", get_users),
+        legacy(Method::POST, "/api/admin/add_course", add_course),
+        legacy(Method::POST, "/api/admin/update_course", update_course),
+        legacy(Method::POST, "/api/admin/delete_course", delete_course),
+    ]);
     
-    if !courses.iter().any(|c| c.id == course_id) {
-        return HttpResponse::BadRequest().json(ErrorResponse { 
-            error: "Course not found in course list".to_string() 
-        });
-    }
+    let student = scope::<Student>(vec![
+        api(Method::GET, "/api/me/schedule", resources::get_my_schedule),
+        api(Method::POST, "/api/me/schedule/swap", resources::swap_my_schedule),
+        api(Method::GET, "/api/me/schedule/approval", approval::approval_status),
+        api(Method::POST, "/api/me/schedule/approval", approval::submit_schedule),
+        api(Method::GET, "/api/me/overrides", overrides::my_overrides),
+        api(Method::POST, "/api/me/overrides", overrides::request_override),
+        api(Method::GET, "/api/me/shares", share::list_share_links),
+        api(Method::POST, "/api/me/shares", share::create_share_link),
+        api(Method::DELETE, "/api/me/shares/{token}", share::revoke_share_link),
+        api(Method::POST, "/api/me/overlay", overlay::schedule_overlay),
+        api(Method::GET, "/api/rooms", rooms::list_rooms),
+        api(Method::POST, "/api/rooms", rooms::create_room),
+        api(Method::GET, "/api/rooms/{id}", rooms::get_room),
+        api(Method::DELETE, "/api/rooms/{id}", rooms::delete_room),
+        api(Method::PUT, "/api/rooms/{id}/members/{username}", rooms::add_member),
+        api(Method::DELETE, "/api/rooms/{id}/members/{username}", rooms::remove_member),
+        api(Method::GET, "/api/rooms/{id}/ws", rooms::room_socket),
+        api(Method::GET, "/api/me/schedule/{course_id}", resources::get_my_schedule_entry),
+        api(Method::PUT, "/api/me/schedule/{course_id}", resources::put_my_schedule_entry),
+        api(Method::PATCH, "/api/me/schedule/{course_id}", resources::patch_my_schedule_entry),
+        api(Method::DELETE, "/api/me/schedule/{course_id}", resources::delete_my_schedule_entry),
+        legacy(Method::POST, "/api/student/add_to_schedule", add_to_schedule),
+        legacy(Method::POST, "/api/student/update_schedule_entry", update_schedule_entry),
+        legacy(Method::POST, "/api/student/drop_from_schedule", drop_from_schedule),
+        legacy(Method::POST, "/api/student/swap_courses", swap_courses),
+        legacy(Method::GET, "/api/student/schedule", get_schedule),
+    ]);
     
-    let mut schedule = state.schedule.lock().unwrap();
-    let initial_len = schedule.courses.len();
-    schedule.courses.retain(|c| c.course_id != course_id);
+    let instructor = scope::<Instructor>(vec![
+        api(Method::GET, "/api/me/courses", instructor::my_courses),
+        api(Method::PATCH, "/api/me/courses/{id}", instructor::patch_my_course),
+        api(Method::GET, "/api/me/courses/{id}/roster", instructor::course_roster),
+        api(Method::GET, "/api/me/courses/{id}/roster.csv", instructor::export_roster),
+    ]);
     
-    if schedule.courses.len() == initial_len {
-        return HttpResponse::Ok().json(&*schedule);
-    }
+    let advisor = scope::<Advisor>(vec![
+        api(Method::GET, "/api/me/advisees", approval::list_advisees),
+        api(Method::GET, "/api/me/approvals", approval::pending_approvals),
+        api(Method::GET, "/api/me/advisees/{username}/schedule", approval::advisee_schedule),
+        api(Method::POST, "/api/me/advisees/{username}/schedule/approve", approval::approve_schedule),
+        api(Method::POST, "/api/me/advisees/{username}/schedule/reject", approval::reject_schedule),
+    ]);
     
-    match save_schedule(&schedule) {
-        Ok(_) => HttpResponse::Ok().json(&*schedule),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse { 
-            error: format!("Failed to save schedule: {}", e) 
-        }),
-    }
+    public.into_iter().chain(admin).chain(student).chain(instructor).chain(advisor).collect()
 }
 
-// Returns the student’s current schedule
-async fn get_schedule(state: web::Data<AppState>) -> impl Responder {
-    let session = state.session.lock().unwrap();
-    if session.as_ref().map_or(true, |s| s.username == "admin") {
-        return HttpResponse::Forbidden().json(ErrorResponse { 
-            error: "Only students can view schedule".to_string() 
-        });
+// Registers the API routes, grouping methods that share a path into one resource
+fn configure_api(cfg: &mut web::ServiceConfig) {
+    let mut grouped: Vec<(&'static str, bool, Vec<Route>)> = Vec::new();
+    for entry in api_routes() {
+        match grouped.iter_mut().find(|(path, _, _)| *path == entry.path) {
+            Some((_, _, routes)) => routes.push(entry.route),
+            None => grouped.push((entry.path, entry.deprecated, vec![entry.route])),
+        }
     }
+    // Actix matches resources in registration order, so fixed paths like /api/courses/import
+    // must come before templates like /api/courses/{id} that would otherwise swallow them
+    grouped.sort_by_key(|(path, _, _)| path.contains('{'));
     
-    let schedule = state.schedule.lock().unwrap();
-    let courses = state.courses.lock().unwrap();
-    
-    let scheduled_courses: Vec<(ScheduledCourse, &Course)> = schedule
-        .courses
-        .iter()
-        .filter_map(|sc| courses.iter().find(|c| c.id == sc.course_id).map(|c| (sc.clone(), c)))
-        .collect();
-    
-    HttpResponse::Ok().json(scheduled_courses)
-}
-
-// Checks if two meeting times conflict
-fn has_time_conflict(time1: &str, time2: &str) -> bool {
-    let parse_time = |time: &str| -> Vec<(String, u32, u32, u32, u32)> {
-        time.split(", ").map(|slot| {
-            let parts: Vec<&str> = slot.split(' ').collect();
-            let day = parts[0].to_string();
-            let times: Vec<&str> = parts[1].split('-').collect();
-            let start: Vec<u32> = times[0].split(':').map(|x| x.parse().unwrap()).collect();
-            let end: Vec<u32> = times[1].split(':').map(|x| x.parse().unwrap()).collect();
-            (day, start[0], start[1], end[0], end[1])
-        }).collect()
-    };
-
-    let slots1 = parse_time(time1);
-    let slots2 = parse_time(time2);
-    
-    for (day1, start_h1, start_m1, end_h1, end_m1) in &slots1 {
-        let start1 = start_h1 * 60 + start_m1;
-        let end1 = end_h1 * 60 + end_m1;
-        
-        for (day2, start_h2, start_m2, end_h2, end_m2) in &slots2 {
-            let start2 = start_h2 * 60 + start_m2;
-            let end2 = end_h2 * 60 + end_m2;
-            
-            if day1 == day2 && !(end1 <= start2 || start1 >= end2) {
-                return true;
-            }
+    for (path, deprecated, routes) in grouped {
+        let resource = routes.into_iter().fold(web::resource(path), |res, route| res.route(route));
+        if deprecated {
+            cfg.service(resource.wrap(DefaultHeaders::new().add(("Deprecation", "true"))));
+        } else {
+            cfg.service(resource);
         }
     }
-    false
 }
 
 // Main entry point for the server
@@ -675,11 +1507,27 @@ fn has_time_conflict(time1: &str, time2: &str) -> bool {
 async fn main() -> std::io::Result<()> {
     fs::remove_file("session.json").unwrap_or(());
     
+    let users = load_users()?;
+    let setup_token = setup_token(&users);
     let state = web::Data::new(AppState {
         courses: Mutex::new(load_courses()),
-        schedule: Mutex::new(load_schedule()),
-        users: Mutex::new(load_users()),
+        schedules: Mutex::new(load_schedules(&users)),
+        users: Mutex::new(users),
         session: Mutex::new(None),
+        overrides: Mutex::new(load_overrides()),
+        audit: Mutex::new(load_audit_log()),
+        course_history: Mutex::new(load_course_history()),
+        notifications: Mutex::new(load_notifications()),
+        mail: load_mail_settings(),
+        events: event_channel(),
+        rooms: Mutex::new(load_rooms()),
+        room_channels: Mutex::new(HashMap::new()),
+        share_links: Mutex::new(load_share_links()),
+        password_resets: Mutex::new(load_password_resets()),
+        login_attempts: Mutex::new(LoginAttempts::default()),
+        two_factor: Mutex::new(load_two_factor()),
+        pending_logins: Mutex::new(Vec::new()),
+        setup_token: Mutex::new(setup_token),
     });
 
     println!("Server running at http://localhost:8080");
@@ -688,22 +1536,132 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || {
         App::new()
             .app_data(state.clone())
-            .route("/api/login", web::post().to(login))
-            .route("/api/logout", web::post().to(logout))
-            .route("/api/admin/add_user", web::post().to(add_user))
-            .route("/api/admin/delete_user", web::post().to(delete_user))
-            .route("/api/admin/get_users", web::get().to(get_users))
-            .route("/api/admin/add_course", web::post().to(add_course))
-            .route("/api/admin/update_course", web::post().to(update_course))
-            .route("/api/admin/delete_course", web::post().to(delete_course))
-            .route("/api/courses", web::get().to(get_courses))
-            .route("/api/student/add_to_schedule", web::post().to(add_to_schedule))
-            .route("/api/student/update_schedule_entry", web::post().to(update_schedule_entry))
-            .route("/api/student/drop_from_schedule", web::post().to(drop_from_schedule))
-            .route("/api/student/schedule", web::get().to(get_schedule))
+            .app_data(web::JsonConfig::default().error_handler(|err, _| {
+                ApiError::InvalidRequest(err.to_string()).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| {
+                ApiError::InvalidRequest(err.to_string()).into()
+            }))
+            .configure(configure_api)
             .service(Files::new("/", "./static").index_file("index.html"))
     })
     .bind("127.0.0.1:8080")?
     .run()
     .await
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A CS course section meeting at the given time
+    fn course(id: u32, course_number: &str, meeting_time: &str) -> Course {
+        Course {
+            id,
+            dept_code: "CS".to_string(),
+            course_number: course_number.to_string(),
+            instructor: "Staff".to_string(),
+            description: String::new(),
+            location: String::new(),
+            meeting_time: meeting_time.to_string(),
+        }
+    }
+
+    // A schedule holding the given course ids
+    fn schedule_of_ids(ids: &[u32]) -> Schedule {
+        Schedule {
+            courses: ids.iter().map(|&course_id| ScheduledCourse { course_id, notes: String::new(), slot: String::new() }).collect(),
+            ..Schedule::default()
+        }
+    }
+
+    #[test]
+    fn split_days_keeps_two_letter_days_together() {
+        assert_eq!(split_days("MWF"), ["M", "W", "F"]);
+        assert_eq!(split_days("TTh"), ["T", "Th"]);
+        assert_eq!(split_days("MTuWThF"), ["M", "Tu", "W", "Th", "F"]);
+        assert!(split_days("").is_empty());
+    }
+
+    #[test]
+    fn parse_meeting_time_expands_day_groups_and_skips_bad_slots() {
+        let slots = parse_meeting_time("MW 10:00-11:15, F 09:00-09:50, Sa 25, Tu 99999999:00-10:00, Th 09:75-10:00");
+        let days: Vec<(&str, u32, u32)> = slots.iter().map(|s| (s.day.as_str(), s.start, s.end)).collect();
+        assert_eq!(days, [("M", 600, 675), ("W", 600, 675), ("F", 540, 590)]);
+    }
+
+    #[test]
+    fn back_to_back_classes_do_not_overlap() {
+        assert!(find_time_overlaps("M 09:00-10:00", "M 10:00-11:00").is_empty());
+        assert!(find_time_overlaps("M 10:00-11:00", "M 09:00-10:00").is_empty());
+    }
+
+    #[test]
+    fn overlaps_report_the_shared_window_per_day() {
+        assert_eq!(find_time_overlaps("MW 09:00-10:30", "W 10:00-11:00"), [("W".to_string(), 600, 630)]);
+        assert_eq!(find_time_overlaps("T 09:00-12:00", "T 10:00-11:00"), [("T".to_string(), 600, 660)]);
+        // "T" and "Th" are different days
+        assert!(find_time_overlaps("T 09:00-10:00", "Th 09:00-10:00").is_empty());
+    }
+
+    #[test]
+    fn schedule_conflicts_ignore_the_course_itself_and_unknown_courses() {
+        let courses = vec![course(1, "101", "MW 09:00-10:00"), course(2, "201", "W 09:30-10:30")];
+        let schedule = schedule_of_ids(&[1, 2, 99]);
+
+        let conflicts = schedule_conflicts(&courses[0], &schedule, &courses);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].course_id, 2);
+        assert_eq!((conflicts[0].overlap_start.as_str(), conflicts[0].overlap_end.as_str()), ("09:30", "10:00"));
+    }
+
+    #[test]
+    fn course_impact_only_counts_new_clashes() {
+        let courses = vec![course(1, "101", "M 09:00-10:00"), course(2, "201", "M 09:30-10:30"), course(3, "301", "W 13:00-14:00")];
+        // ana took 101 with a time conflict override, ben has no clash yet
+        let schedules = HashMap::from([("ana".to_string(), schedule_of_ids(&[1, 2])), ("ben".to_string(), schedule_of_ids(&[1, 3]))]);
+
+        let moved = course(1, "101", "M 09:15-10:15");
+        let impact = course_impact(&courses, &schedules, 1, Some(&moved));
+        assert_eq!(impact.affected_students, ["ana", "ben"]);
+        assert!(impact.conflicting_students.is_empty());
+
+        let moved = course(1, "101", "MW 09:00-13:30");
+        let impact = course_impact(&courses, &schedules, 1, Some(&moved));
+        let conflicting: Vec<(&str, Vec<u32>)> =
+            impact.conflicting_students.iter().map(|s| (s.username.as_str(), s.conflicts.iter().map(|c| c.course_id).collect())).collect();
+        assert_eq!(conflicting, [("ben", vec![3])]);
+    }
+
+    #[test]
+    fn validate_course_keeps_classes_within_the_teaching_day() {
+        let new_course = |meeting_time: &str| NewCourse {
+            dept_code: "CS".to_string(),
+            course_number: "101".to_string(),
+            instructor: "Staff".to_string(),
+            description: String::new(),
+            location: String::new(),
+            meeting_time: meeting_time.to_string(),
+        };
+        assert!(validate_course(&new_course("M 08:00-21:00")).is_ok());
+        assert!(validate_course(&new_course("M 07:59-09:00")).is_err());
+        assert!(validate_course(&new_course("M 20:00-21:01")).is_err());
+        assert!(validate_course(&new_course("M 10:00-10:00")).is_err());
+        assert!(validate_course(&new_course("M 4294967295:00-10:00")).is_err());
+        assert!(validate_course(&new_course("M 09:75-10:00")).is_err());
+    }
+
+    #[test]
+    fn alternative_sections_only_suggest_sections_that_fit() {
+        let courses = vec![
+            course(1, "101", "M 09:00-10:00"),
+            course(2, "201", "M 09:00-10:00"),
+            course(3, "201", "M 09:30-10:30"),
+            course(4, "201", "M 10:00-11:00"),
+            course(5, "301", "T 09:00-10:00"),
+        ];
+        let schedule = schedule_of_ids(&[1]);
+
+        let alternatives: Vec<u32> = alternative_sections(&courses[1], &schedule, &courses).iter().map(|c| c.id).collect();
+        assert_eq!(alternatives, [4]);
+    }
+}
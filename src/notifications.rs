@@ -0,0 +1,180 @@
+// Per-user notification inbox. Course changes, schedule reviews and override
+// decisions leave a notification for the users they affect, which they can
+// list and mark as read.
+use std::fs;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::events::{publish_to, Event};
+use super::mailer::send_notification_emails;
+use super::{AppState, Course};
+
+// What a notification is about
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    CourseUpdated,
+    CourseDropped,
+    CourseDeleted,
+    CourseRestored,
+    ScheduleSubmitted,
+    ScheduleApproved,
+    ScheduleRejected,
+    OverrideGranted,
+    OverrideDenied,
+}
+
+// One message in a user's inbox
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct Notification {
+    id: u64,
+    username: String,
+    created_at: DateTime<Utc>,
+    kind: NotificationKind,
+    message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    course_id: Option<u32>,
+    read: bool,
+}
+
+// Filter for listing notifications
+#[derive(Deserialize, Debug, IntoParams)]
+pub struct NotificationQuery {
+    // Only list notifications that haven't been read
+    #[serde(default)]
+    unread: bool,
+}
+
+// Loads notifications from file
+pub fn load_notifications() -> Vec<Notification> {
+    fs::read_to_string("notifications.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves notifications
+fn save_notifications(notifications: &Vec<Notification>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(notifications)?;
+    fs::write("notifications.json", data)?;
+    Ok(())
+}
+
+// Short name for a course in messages, e.g. "CS 101"
+pub fn course_label(course: &Course) -> String {
+    format!("{} {}", course.dept_code, course.course_number)
+}
+
+// Leaves a notification for each of the given users and emails those who opted in
+pub fn notify(state: &AppState, usernames: &[String], kind: NotificationKind, course_id: Option<u32>, message: &str) {
+    if usernames.is_empty() {
+        return;
+    }
+
+    let mut notifications = state.notifications.lock().unwrap();
+    for username in usernames {
+        let notification = Notification {
+            id: notifications.last().map_or(1, |n| n.id + 1),
+            username: username.clone(),
+            created_at: Utc::now(),
+            kind,
+            message: message.to_string(),
+            course_id,
+            read: false,
+        };
+        publish_to(state, username, Event::Notification { notification: notification.clone() });
+        notifications.push(notification);
+    }
+
+    // The change being reported already happened, so a failed write is logged rather than returned
+    if let Err(e) = save_notifications(&notifications) {
+        println!("Failed to save notifications: {:?}", e);
+    }
+    drop(notifications);
+
+    send_notification_emails(state, usernames, kind, message);
+}
+
+// Drops every notification for a user, e.g. when the account is deleted
+pub fn clear_notifications(state: &AppState, username: &str) -> Result<(), ApiError> {
+    let mut notifications = state.notifications.lock().unwrap();
+    let count = notifications.len();
+    notifications.retain(|n| n.username != username);
+    if notifications.len() != count {
+        save_notifications(&notifications).map_err(|e| ApiError::storage("notifications", e))?;
+    }
+    Ok(())
+}
+
+// GET /api/me/notifications
+#[utoipa::path(
+    get,
+    path = "/api/me/notifications",
+    tag = "notifications",
+    params(NotificationQuery),
+    responses(
+        (status = 200, description = "The logged-in user's notifications, newest first", body = Vec<Notification>),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+    )
+)]
+pub async fn list_notifications(user: AuthUser, query: web::Query<NotificationQuery>, state: web::Data<AppState>) -> impl Responder {
+    let notifications = state.notifications.lock().unwrap();
+    let mine: Vec<&Notification> = notifications
+        .iter()
+        .rev()
+        .filter(|n| n.username == user.username && !(query.unread && n.read))
+        .collect();
+    HttpResponse::Ok().json(mine)
+}
+
+// POST /api/me/notifications/{id}/read
+#[utoipa::path(
+    post,
+    path = "/api/me/notifications/{id}/read",
+    tag = "notifications",
+    params(("id" = u64, Path, description = "Notification ID")),
+    responses(
+        (status = 200, description = "Notification marked as read", body = Notification),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+        (status = 404, description = "Notification not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn mark_read(user: AuthUser, id: web::Path<u64>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut notifications = state.notifications.lock().unwrap();
+    let notification = notifications
+        .iter_mut()
+        .find(|n| n.id == *id && n.username == user.username)
+        .ok_or(ApiError::NotificationNotFound)?;
+    notification.read = true;
+    let notification = notification.clone();
+
+    save_notifications(&notifications).map_err(|e| ApiError::storage("notifications", e))?;
+    Ok(HttpResponse::Ok().json(notification))
+}
+
+// POST /api/me/notifications/read
+#[utoipa::path(
+    post,
+    path = "/api/me/notifications/read",
+    tag = "notifications",
+    responses(
+        (status = 204, description = "Every notification marked as read"),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn mark_all_read(user: AuthUser, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut notifications = state.notifications.lock().unwrap();
+    for notification in notifications.iter_mut().filter(|n| n.username == user.username) {
+        notification.read = true;
+    }
+
+    save_notifications(&notifications).map_err(|e| ApiError::storage("notifications", e))?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,240 @@
+// OpenAPI description of the HTTP API, built from the handler annotations and
+// request/response types. Served at /api/openapi.json.
+use actix_web::{HttpResponse, Responder};
+use utoipa::OpenApi;
+
+use super::audit::AuditEntry;
+use super::approval::{AdviseeSchedule, AdviseeSummary, AdvisorAssignment, ApprovalStatus, ReviewNote};
+use super::auth::{AuthUser, Role};
+use super::error::{ApiErrorBody, ErrorCode};
+use super::events::Event;
+use super::export::{CourseEnrollment, ExportFormat, ExportedCourse, ExportedMeeting};
+use super::history::{CourseVersion, FieldChange, RemovedEntry, RestoreOutcome, VersionChange};
+use super::import::{ImportFormat, ImportReport, RowError};
+use super::instructor::RosterEntry;
+use super::mailer::EmailSettings;
+use super::notifications::{Notification, NotificationKind};
+use super::overlay::{BusySlot, FreeWindow, Overlay, OverlayRequest};
+use super::overrides::{NewOverrideRequest, OverrideDecision, OverrideRequest, OverrideRule, OverrideStatus};
+use super::passwords::{PasswordChange, ResetConfirm, ResetRequest};
+use super::provisioning::{Activation, ActivateRequest, CredentialKind, ProvisionReport, ProvisionedUser};
+use super::rooms::{NewRoom, PlanningRoom, RoomAction, RoomEvent};
+use super::setup::{InitialAdmin, SetupStatus};
+use super::share::{NewShareLink, ShareLink, SharedSchedule};
+use super::throttle::{Lockout, LockoutKind};
+use super::two_factor::{RecoveryCodes, TwoFactorCode, TwoFactorDisable, TwoFactorLogin, TwoFactorSetup, TwoFactorStart, TwoFactorStatus};
+use super::{
+    ConflictDetail, ConflictDetails, Course, CourseChange, CourseDescription, CourseImpact, CoursePatch, LoginRequest,
+    LoginResponse, NewCourse, Schedule, ScheduleComment, SchedulePatch, ScheduleStatus, ScheduledCourse,
+    StudentConflicts, SwapRequest, UpdateCourse, User, UserPassword, UserPatch,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Course Scheduler API"),
+    paths(
+        openapi_json,
+        crate::login,
+        crate::two_factor::login_two_factor,
+        crate::setup::setup_status,
+        crate::setup::create_initial_admin,
+        crate::logout,
+        crate::whoami,
+        crate::passwords::change_password,
+        crate::passwords::request_password_reset,
+        crate::passwords::confirm_password_reset,
+        crate::two_factor::two_factor_status,
+        crate::two_factor::start_enrollment,
+        crate::two_factor::confirm_enrollment,
+        crate::two_factor::regenerate_recovery_codes,
+        crate::two_factor::disable_two_factor,
+        crate::provisioning::activate_account,
+        crate::provisioning::provision_users,
+        crate::throttle::list_lockouts,
+        crate::throttle::unlock_account,
+        crate::two_factor::reset_two_factor,
+        crate::get_courses,
+        crate::resources::post_course,
+        crate::import::import_courses,
+        crate::export::export_courses,
+        crate::export::export_enrollment,
+        crate::resources::get_course,
+        crate::resources::put_course,
+        crate::resources::patch_course,
+        crate::resources::delete_course,
+        crate::history::undelete_course,
+        crate::history::list_versions,
+        crate::history::get_version,
+        crate::history::diff_version,
+        crate::history::restore_course_version,
+        crate::resources::list_users,
+        crate::resources::post_user,
+        crate::resources::get_user,
+        crate::resources::put_user,
+        crate::resources::patch_user,
+        crate::resources::delete_user,
+        crate::approval::assign_advisor,
+        crate::approval::unassign_advisor,
+        crate::audit::query_audit_log,
+        crate::notifications::list_notifications,
+        crate::notifications::mark_read,
+        crate::notifications::mark_all_read,
+        crate::mailer::get_email_settings,
+        crate::mailer::put_email_settings,
+        crate::events::event_stream,
+        crate::rooms::list_rooms,
+        crate::rooms::create_room,
+        crate::rooms::get_room,
+        crate::rooms::delete_room,
+        crate::rooms::add_member,
+        crate::rooms::remove_member,
+        crate::rooms::room_socket,
+        crate::share::list_share_links,
+        crate::share::create_share_link,
+        crate::share::revoke_share_link,
+        crate::share::shared_json,
+        crate::share::shared_html,
+        crate::share::shared_ics,
+        crate::overlay::schedule_overlay,
+        crate::overrides::list_overrides,
+        crate::overrides::grant_override,
+        crate::overrides::deny_override,
+        crate::resources::get_my_schedule,
+        crate::resources::swap_my_schedule,
+        crate::resources::get_my_schedule_entry,
+        crate::resources::put_my_schedule_entry,
+        crate::resources::patch_my_schedule_entry,
+        crate::resources::delete_my_schedule_entry,
+        crate::approval::approval_status,
+        crate::approval::submit_schedule,
+        crate::overrides::my_overrides,
+        crate::overrides::request_override,
+        crate::instructor::my_courses,
+        crate::instructor::patch_my_course,
+        crate::instructor::course_roster,
+        crate::instructor::export_roster,
+        crate::approval::list_advisees,
+        crate::approval::pending_approvals,
+        crate::approval::advisee_schedule,
+        crate::approval::approve_schedule,
+        crate::approval::reject_schedule,
+        crate::add_user,
+        crate::delete_user,
+        crate::get_users,
+        crate::add_course,
+        crate::update_course,
+        crate::delete_course,
+        crate::add_to_schedule,
+        crate::update_schedule_entry,
+        crate::drop_from_schedule,
+        crate::swap_courses,
+        crate::get_schedule,
+    ),
+    components(schemas(
+        Course, NewCourse, UpdateCourse, CoursePatch, ScheduledCourse, Schedule, SchedulePatch, SwapRequest,
+        User, UserPassword, UserPatch, LoginRequest, LoginResponse, ApiErrorBody, ErrorCode, ConflictDetails,
+        ConflictDetail, AuthUser, Role, CourseDescription, RosterEntry, ScheduleStatus, ScheduleComment,
+        AdvisorAssignment, ReviewNote, ApprovalStatus, AdviseeSummary, AdviseeSchedule, OverrideRule,
+        OverrideStatus, OverrideRequest, NewOverrideRequest, OverrideDecision, AuditEntry,
+        VersionChange, RemovedEntry, CourseVersion, FieldChange, RestoreOutcome, CourseImpact, CourseChange, StudentConflicts,
+        Notification, NotificationKind, EmailSettings, Event, PlanningRoom, NewRoom, RoomAction, RoomEvent,
+        ShareLink, NewShareLink, SharedSchedule, OverlayRequest, Overlay, BusySlot, FreeWindow,
+        ImportFormat, ImportReport, RowError, ExportFormat, ExportedCourse, ExportedMeeting, CourseEnrollment,
+        Activation, ActivateRequest, CredentialKind, ProvisionReport, ProvisionedUser, PasswordChange,
+        ResetRequest, ResetConfirm, Lockout, LockoutKind,
+        TwoFactorStatus, TwoFactorStart, TwoFactorSetup, TwoFactorCode, TwoFactorDisable, RecoveryCodes, TwoFactorLogin,
+        SetupStatus, InitialAdmin,
+    ))
+)]
+pub struct ApiDoc;
+
+// GET /api/openapi.json
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "docs",
+    responses(
+        (status = 200, description = "OpenAPI 3 document for this API"),
+    )
+)]
+pub async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_routes;
+    use actix_web::http::Method;
+    use utoipa::openapi::path::{HttpMethod, PathItem};
+
+    // Looks up the documented operation for a method on a path item
+    fn has_operation(item: &PathItem, method: &Method) -> bool {
+        let operation = match *method {
+            Method::GET => &item.get,
+            Method::POST => &item.post,
+            Method::PUT => &item.put,
+            Method::PATCH => &item.patch,
+            Method::DELETE => &item.delete,
+            _ => return false,
+        };
+        operation.is_some()
+    }
+
+    #[test]
+    fn every_registered_route_is_documented() {
+        let doc = ApiDoc::openapi();
+        let missing: Vec<String> = api_routes()
+            .iter()
+            .filter(|r| !doc.paths.paths.get(r.path).is_some_and(|item| has_operation(item, &r.method)))
+            .map(|r| format!("{} {}", r.method, r.path))
+            .collect();
+
+        assert!(missing.is_empty(), "routes missing from the OpenAPI document: {:?}", missing);
+    }
+
+    #[test]
+    fn every_documented_operation_is_registered() {
+        let routes = api_routes();
+        let doc = ApiDoc::openapi();
+        let methods = [
+            (HttpMethod::Get, Method::GET),
+            (HttpMethod::Post, Method::POST),
+            (HttpMethod::Put, Method::PUT),
+            (HttpMethod::Patch, Method::PATCH),
+            (HttpMethod::Delete, Method::DELETE),
+        ];
+
+        for (path, item) in &doc.paths.paths {
+            for (_, method) in methods.iter().filter(|(_, m)| has_operation(item, m)) {
+                assert!(
+                    routes.iter().any(|r| r.path == path && r.method == *method),
+                    "documented operation {} {} is not registered",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn deprecated_routes_are_marked_in_the_document() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for route in api_routes().iter().filter(|r| r.deprecated) {
+            let method = route.method.as_str().to_lowercase();
+            let deprecated = &doc["paths"][route.path][&method]["deprecated"];
+            assert_eq!(deprecated, true, "{} {} should be documented as deprecated", route.method, route.path);
+        }
+    }
+
+    #[test]
+    fn course_changes_document_the_dry_run_body() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for method in ["put", "patch"] {
+            let schema = &doc["paths"]["/api/courses/{id}"][method]["responses"]["200"]["content"]["application/json"]["schema"];
+            assert_eq!(schema["$ref"], "#/components/schemas/CourseChange");
+        }
+        let variants: Vec<&serde_json::Value> = doc["components"]["schemas"]["CourseChange"]["oneOf"].as_array().unwrap().iter().map(|v| &v["$ref"]).collect();
+        assert_eq!(variants, ["#/components/schemas/Course", "#/components/schemas/CourseImpact"]);
+    }
+}
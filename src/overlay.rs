@@ -0,0 +1,185 @@
+// Overlays the logged-in student's schedule with schedules friends shared with
+// them, to find the times everyone is free and the courses everyone could add
+// without a conflict.
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::share::shared_student;
+use super::{format_minutes, parse_meeting_time, schedule_conflicts, schedule_of, AppState, Course, Schedule, DAY_END, DAY_START};
+
+// Weekdays free time is computed for
+const WEEKDAYS: [&str; 5] = ["M", "T", "W", "Th", "F"];
+
+// Most share links accepted in one overlay
+const MAX_LINKS: usize = 20;
+
+// Share links of the schedules to overlay with your own
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct OverlayRequest {
+    tokens: Vec<String>,
+    // Shortest free window worth reporting, in minutes
+    #[serde(default)]
+    min_minutes: u32,
+}
+
+// One student's class meeting
+#[derive(Serialize, Debug, ToSchema)]
+pub struct BusySlot {
+    student: String,
+    course_id: u32,
+    course: String,
+    day: String,
+    start: String,
+    end: String,
+}
+
+// A window when nobody has class
+#[derive(Serialize, Debug, ToSchema)]
+pub struct FreeWindow {
+    day: String,
+    start: String,
+    end: String,
+}
+
+// Everyone's classes, the windows they are all free and the courses they could all take
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Overlay {
+    students: Vec<String>,
+    busy: Vec<BusySlot>,
+    free_time: Vec<FreeWindow>,
+    common_courses: Vec<Course>,
+}
+
+// Finds the gaps between busy intervals within the class day
+fn free_windows(day: &str, mut busy: Vec<(u32, u32)>, min_minutes: u32) -> Vec<FreeWindow> {
+    busy.sort();
+    let mut windows = Vec::new();
+    let mut free_from = DAY_START;
+    for (start, end) in busy.into_iter().chain([(DAY_END, DAY_END)]) {
+        let start = start.clamp(DAY_START, DAY_END);
+        if start > free_from && start - free_from >= min_minutes.max(1) {
+            windows.push(FreeWindow { day: day.to_string(), start: format_minutes(free_from), end: format_minutes(start) });
+        }
+        free_from = free_from.max(end.min(DAY_END));
+    }
+    windows
+}
+
+// Builds the overlay of the given students' schedules
+fn build_overlay(state: &AppState, students: Vec<String>, min_minutes: u32) -> Overlay {
+    let schedules: Vec<(String, Schedule)> = students.iter().map(|s| (s.clone(), schedule_of(state, s))).collect();
+    let courses = state.courses.lock().unwrap();
+
+    let mut busy = Vec::new();
+    for (student, schedule) in &schedules {
+        for course in schedule.courses.iter().filter_map(|sc| courses.iter().find(|c| c.id == sc.course_id)) {
+            for slot in parse_meeting_time(&course.meeting_time) {
+                busy.push((student.clone(), course, slot));
+            }
+        }
+    }
+
+    let free_time = WEEKDAYS
+        .iter()
+        .flat_map(|day| {
+            let intervals = busy.iter().filter(|(_, _, s)| s.day == *day).map(|(_, _, s)| (s.start, s.end)).collect();
+            free_windows(day, intervals, min_minutes)
+        })
+        .collect();
+
+    // A course someone already has still counts, the others could join them in it
+    let common_courses = courses
+        .iter()
+        .filter(|course| schedules.iter().all(|(_, schedule)| schedule_conflicts(course, schedule, &courses).is_empty()))
+        .cloned()
+        .collect();
+
+    let busy = busy
+        .into_iter()
+        .map(|(student, course, slot)| BusySlot {
+            student,
+            course_id: course.id,
+            course: format!("{} {}", course.dept_code, course.course_number),
+            day: slot.day,
+            start: format_minutes(slot.start),
+            end: format_minutes(slot.end),
+        })
+        .collect();
+
+    Overlay { students, busy, free_time, common_courses }
+}
+
+// POST /api/me/overlay
+#[utoipa::path(
+    post,
+    path = "/api/me/overlay",
+    tag = "schedule",
+    request_body = OverlayRequest,
+    responses(
+        (status = 200, description = "Your schedule overlaid with the shared ones", body = Overlay),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "A share link is unknown, revoked or expired", body = ApiErrorBody),
+    )
+)]
+pub async fn schedule_overlay(user: AuthUser, body: web::Json<OverlayRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    if body.tokens.is_empty() {
+        return Err(ApiError::validation("tokens", "Add at least one share link"));
+    }
+    if body.tokens.len() > MAX_LINKS {
+        return Err(ApiError::validation("tokens", &format!("Too many share links, the limit is {}", MAX_LINKS)));
+    }
+
+    let mut students = vec![user.username.clone()];
+    for token in &body.tokens {
+        let student = shared_student(&state, token)?;
+        if !students.contains(&student) {
+            students.push(student);
+        }
+    }
+    Ok(HttpResponse::Ok().json(build_overlay(&state, students, body.min_minutes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Windows as (start, end) for easy comparison
+    fn spans(windows: &[FreeWindow]) -> Vec<(&str, &str)> {
+        windows.iter().map(|w| (w.start.as_str(), w.end.as_str())).collect()
+    }
+
+    #[test]
+    fn overlapping_and_touching_classes_merge() {
+        let busy = vec![(600, 690), (540, 630), (690, 720), (800, 860), (810, 840)];
+        let windows = free_windows("M", busy, 0);
+
+        assert!(windows.iter().all(|w| w.day == "M"));
+        assert_eq!(spans(&windows), [("08:00", "09:00"), ("12:00", "13:20"), ("14:20", "21:00")]);
+    }
+
+    #[test]
+    fn windows_are_clipped_to_the_class_day() {
+        let busy = vec![(7 * 60, 9 * 60), (20 * 60, 22 * 60), (22 * 60, 23 * 60)];
+        assert_eq!(spans(&free_windows("T", busy, 0)), [("09:00", "20:00")]);
+        assert_eq!(spans(&free_windows("T", Vec::new(), 0)), [("08:00", "21:00")]);
+    }
+
+    #[test]
+    fn short_windows_are_left_out() {
+        let busy = vec![(8 * 60 + 30, 10 * 60), (10 * 60 + 15, 20 * 60 + 59)];
+        assert_eq!(spans(&free_windows("W", busy.clone(), 0)), [("08:00", "08:30"), ("10:00", "10:15"), ("20:59", "21:00")]);
+        assert_eq!(spans(&free_windows("W", busy.clone(), 15)), [("08:00", "08:30"), ("10:00", "10:15")]);
+        assert_eq!(spans(&free_windows("W", busy, 16)), [("08:00", "08:30")]);
+    }
+
+    #[test]
+    fn a_fully_booked_day_has_no_windows() {
+        assert!(free_windows("F", vec![(DAY_START, 12 * 60), (11 * 60, DAY_END)], 0).is_empty());
+        assert!(free_windows("F", vec![(7 * 60, 22 * 60)], 0).is_empty());
+    }
+}
@@ -0,0 +1,228 @@
+// Registrar overrides. A student asks to be let past a rule that blocks them
+// from adding a specific course, an admin grants or denies the request, and a
+// granted override lets `schedule_course` skip that rule for that course.
+use actix_web::{web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::notifications::{notify, NotificationKind};
+use super::{save_overrides, AppState};
+
+// A scheduling rule that can be overridden
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OverrideRule {
+    TimeConflict,
+}
+
+// Where an override request stands
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OverrideStatus {
+    Pending,
+    Granted,
+    Denied,
+}
+
+// A student's request to bypass one rule for one course
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct OverrideRequest {
+    id: u32,
+    student: String,
+    course_id: u32,
+    rule: OverrideRule,
+    reason: String,
+    status: OverrideStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    response: Option<String>,
+}
+
+// Data a student sends to ask for an override
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct NewOverrideRequest {
+    course_id: u32,
+    rule: OverrideRule,
+    reason: String,
+}
+
+// Optional note from the admin when deciding a request
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct OverrideDecision {
+    comment: Option<String>,
+}
+
+// Filter for listing override requests
+#[derive(Deserialize, Debug)]
+pub struct OverrideQuery {
+    status: Option<OverrideStatus>,
+}
+
+// Whether the student holds a granted override of `rule` for the course
+pub fn has_granted_override(state: &AppState, student: &str, course_id: u32, rule: OverrideRule) -> bool {
+    let overrides = state.overrides.lock().unwrap();
+    overrides.iter().any(|o| {
+        o.student == student && o.course_id == course_id && o.rule == rule && o.status == OverrideStatus::Granted
+    })
+}
+
+// Grants or denies a pending request
+fn decide(state: &AppState, id: u32, admin: &str, status: OverrideStatus, comment: Option<String>) -> Result<OverrideRequest, ApiError> {
+    let mut overrides = state.overrides.lock().unwrap();
+    let request = overrides.iter_mut().find(|o| o.id == id).ok_or(ApiError::OverrideNotFound)?;
+    if request.status != OverrideStatus::Pending {
+        return Err(ApiError::OverrideDecided);
+    }
+
+    let before = request.clone();
+    request.status = status;
+    request.decided_by = Some(admin.to_string());
+    request.response = comment.filter(|text| !text.trim().is_empty());
+    let request = request.clone();
+
+    save_overrides(&overrides).map_err(|e| ApiError::storage("overrides", e))?;
+    let action = if status == OverrideStatus::Granted { "override.grant" } else { "override.deny" };
+    audit::record(state, admin, action, format!("override:{}", id), Some(&before), Some(&request));
+    drop(overrides);
+
+    let (kind, outcome) = match status {
+        OverrideStatus::Granted => (NotificationKind::OverrideGranted, "granted"),
+        _ => (NotificationKind::OverrideDenied, "denied"),
+    };
+    let mut message = format!("Your override request for course {} was {}", request.course_id, outcome);
+    if let Some(response) = &request.response {
+        message.push_str(&format!(": {}", response));
+    }
+    notify(state, std::slice::from_ref(&request.student), kind, Some(request.course_id), &message);
+    Ok(request)
+}
+
+// GET /api/me/overrides
+#[utoipa::path(
+    get,
+    path = "/api/me/overrides",
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Override requests made by the logged-in student", body = Vec<OverrideRequest>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn my_overrides(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    let overrides = state.overrides.lock().unwrap();
+    let mine: Vec<&OverrideRequest> = overrides.iter().filter(|o| o.student == user.username).collect();
+    HttpResponse::Ok().json(mine)
+}
+
+// POST /api/me/overrides
+#[utoipa::path(
+    post,
+    path = "/api/me/overrides",
+    tag = "schedule",
+    request_body = NewOverrideRequest,
+    responses(
+        (status = 200, description = "A pending request for this course and rule already exists", body = OverrideRequest),
+        (status = 201, description = "Override requested", body = OverrideRequest),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn request_override(user: AuthUser, body: web::Json<NewOverrideRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    if !state.courses.lock().unwrap().iter().any(|c| c.id == body.course_id) {
+        return Err(ApiError::CourseNotFound);
+    }
+    if body.reason.trim().is_empty() {
+        return Err(ApiError::validation("reason", "Explain why the override is needed"));
+    }
+
+    let mut overrides = state.overrides.lock().unwrap();
+    if let Some(existing) = overrides.iter().find(|o| {
+        o.student == user.username
+            && o.course_id == body.course_id
+            && o.rule == body.rule
+            && o.status == OverrideStatus::Pending
+    }) {
+        return Ok(HttpResponse::Ok().json(existing));
+    }
+
+    let request = OverrideRequest {
+        id: overrides.iter().map(|o| o.id).max().unwrap_or(0) + 1,
+        student: user.username.clone(),
+        course_id: body.course_id,
+        rule: body.rule,
+        reason: body.reason,
+        status: OverrideStatus::Pending,
+        decided_by: None,
+        response: None,
+    };
+    overrides.push(request.clone());
+    save_overrides(&overrides).map_err(|e| ApiError::storage("overrides", e))?;
+    audit::record(&state, &user.username, "override.request", format!("override:{}", request.id), None::<&OverrideRequest>, Some(&request));
+    Ok(HttpResponse::Created().json(request))
+}
+
+// GET /api/overrides
+#[utoipa::path(
+    get,
+    path = "/api/overrides",
+    tag = "overrides",
+    params(("status" = Option<OverrideStatus>, Query, description = "Only list requests with this status")),
+    responses(
+        (status = 200, description = "Override requests", body = Vec<OverrideRequest>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_overrides(query: web::Query<OverrideQuery>, state: web::Data<AppState>) -> impl Responder {
+    let overrides = state.overrides.lock().unwrap();
+    let matching: Vec<&OverrideRequest> = overrides
+        .iter()
+        .filter(|o| query.status.is_none_or(|status| o.status == status))
+        .collect();
+    HttpResponse::Ok().json(matching)
+}
+
+// POST /api/overrides/{id}/grant
+#[utoipa::path(
+    post,
+    path = "/api/overrides/{id}/grant",
+    tag = "overrides",
+    params(("id" = u32, Path, description = "Override request ID")),
+    request_body = OverrideDecision,
+    responses(
+        (status = 200, description = "Override granted", body = OverrideRequest),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Override request not found", body = ApiErrorBody),
+        (status = 409, description = "Request was already decided", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn grant_override(user: AuthUser, id: web::Path<u32>, body: web::Json<OverrideDecision>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let request = decide(&state, id.into_inner(), &user.username, OverrideStatus::Granted, body.into_inner().comment)?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+// POST /api/overrides/{id}/deny
+#[utoipa::path(
+    post,
+    path = "/api/overrides/{id}/deny",
+    tag = "overrides",
+    params(("id" = u32, Path, description = "Override request ID")),
+    request_body = OverrideDecision,
+    responses(
+        (status = 200, description = "Override denied", body = OverrideRequest),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Override request not found", body = ApiErrorBody),
+        (status = 409, description = "Request was already decided", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn deny_override(user: AuthUser, id: web::Path<u32>, body: web::Json<OverrideDecision>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let request = decide(&state, id.into_inner(), &user.username, OverrideStatus::Denied, body.into_inner().comment)?;
+    Ok(HttpResponse::Ok().json(request))
+}
@@ -0,0 +1,243 @@
+// Self-service passwords. Logged-in users can change their password, and
+// anyone can ask for a reset code, which is emailed through the configured
+// mailer (SMTP, or a maildir for testing). Codes are single-use, expire after
+// a short time and are only stored hashed. New passwords must meet the
+// strength rules below.
+use std::fs;
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::mailer::send_email;
+use super::share::new_token;
+use super::{save_users, AppState, User};
+
+// How long a reset code can be used
+const RESET_MINUTES: i64 = 30;
+
+// Shortest and longest passwords accepted
+const MIN_PASSWORD_LENGTH: usize = 8;
+const MAX_PASSWORD_LENGTH: usize = 128;
+
+// Passwords too common to allow, compared ignoring case
+const COMMON_PASSWORDS: [&str; 12] = [
+    "password", "password1", "password123", "12345678", "123456789", "1234567890",
+    "qwerty123", "iloveyou", "letmein1", "welcome1", "admin123", "abc12345",
+];
+
+// An outstanding reset code for an account
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PasswordReset {
+    username: String,
+    // SHA-256 of the code, so the file can't be used to take over accounts
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+// The logged-in user's current password and the one to replace it with
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct PasswordChange {
+    current_password: String,
+    new_password: String,
+}
+
+// Asks for a reset code for an account
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ResetRequest {
+    username: String,
+}
+
+// Sets a new password with a reset code
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ResetConfirm {
+    token: String,
+    new_password: String,
+}
+
+// Loads outstanding reset codes
+pub fn load_password_resets() -> Vec<PasswordReset> {
+    fs::read_to_string("password_resets.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves outstanding reset codes
+fn save_password_resets(resets: &Vec<PasswordReset>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(resets)?;
+    fs::write("password_resets.json", data)?;
+    Ok(())
+}
+
+// Hashes a single-use code the way it is stored
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// Cancels a user's outstanding reset codes, e.g. when their password changes or the account is deleted
+pub fn revoke_password_resets(state: &AppState, username: &str) -> Result<(), ApiError> {
+    let mut resets = state.password_resets.lock().unwrap();
+    let count = resets.len();
+    resets.retain(|r| r.username != username && r.expires_at > Utc::now());
+    if resets.len() != count {
+        save_password_resets(&resets).map_err(|e| ApiError::storage("password resets", e))?;
+    }
+    Ok(())
+}
+
+// Checks a new password against the strength rules
+pub fn validate_password_strength(field: &'static str, username: &str, password: &str) -> Result<(), ApiError> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(ApiError::validation(field, &format!("Use at least {} characters", MIN_PASSWORD_LENGTH)));
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(ApiError::validation(field, &format!("Use at most {} characters", MAX_PASSWORD_LENGTH)));
+    }
+    if !password.chars().any(char::is_alphabetic) || password.chars().all(char::is_alphabetic) {
+        return Err(ApiError::validation(field, "Use both letters and numbers or symbols"));
+    }
+    if username.chars().count() >= 3 && password.to_lowercase().contains(&username.to_lowercase()) {
+        return Err(ApiError::validation(field, "The password can't contain your username"));
+    }
+    if COMMON_PASSWORDS.contains(&password.to_lowercase().as_str()) {
+        return Err(ApiError::validation(field, "That password is too common"));
+    }
+    Ok(())
+}
+
+// Replaces a user's password, after which it no longer has to be changed
+fn set_password(state: &AppState, actor: &str, action: &str, username: &str, password: String) -> Result<(), ApiError> {
+    let mut users = state.users.lock().unwrap();
+    let account = users.iter_mut().find(|u| u.username == username).ok_or(ApiError::UserNotFound)?;
+    let before = account.clone();
+    account.password = password;
+    account.must_change_password = false;
+    let after = account.clone();
+
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    drop(users);
+    audit::record(state, actor, action, format!("user:{}", username), Some(&before), Some(&after));
+    revoke_password_resets(state, username)
+}
+
+// POST /api/me/password
+#[utoipa::path(
+    post,
+    path = "/api/me/password",
+    tag = "auth",
+    request_body = PasswordChange,
+    responses(
+        (status = 200, description = "Password changed"),
+        (status = 400, description = "Invalid input, or the new password is too weak", body = ApiErrorBody),
+        (status = 401, description = "Not logged in, or the current password is wrong", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn change_password(user: AuthUser, body: web::Json<PasswordChange>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let current = state.users.lock().unwrap().iter().find(|u| u.username == user.username).map(|u| u.password.clone());
+    if current.as_ref() != Some(&body.current_password) {
+        return Err(ApiError::InvalidCredentials);
+    }
+    if body.new_password == body.current_password {
+        return Err(ApiError::validation("new_password", "The new password must differ from the current one"));
+    }
+    validate_password_strength("new_password", &user.username, &body.new_password)?;
+
+    set_password(&state, &user.username, "user.password", &user.username, body.new_password)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Password changed"})))
+}
+
+// POST /api/password/reset
+#[utoipa::path(
+    post,
+    path = "/api/password/reset",
+    tag = "auth",
+    request_body = ResetRequest,
+    responses(
+        (status = 200, description = "A reset code was emailed if the account exists and has an email address"),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn request_password_reset(body: web::Json<ResetRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    // The answer is the same either way, so it can't be used to find out which accounts exist
+    let sent = HttpResponse::Ok().json(serde_json::json!({
+        "message": "If the account has an email address, a reset code was sent to it"
+    }));
+    let email = {
+        let users = state.users.lock().unwrap();
+        users
+            .iter()
+            .find(|u| u.username == body.username && u.activation.is_none())
+            .and_then(|u| u.email.clone())
+    };
+    let Some(email) = email else {
+        return Ok(sent);
+    };
+
+    // A new code replaces any earlier one
+    let token = new_token();
+    {
+        let mut resets = state.password_resets.lock().unwrap();
+        resets.retain(|r| r.username != body.username && r.expires_at > Utc::now());
+        resets.push(PasswordReset {
+            username: body.username.clone(),
+            token_hash: hash_token(&token),
+            expires_at: Utc::now() + Duration::minutes(RESET_MINUTES),
+        });
+        save_password_resets(&resets).map_err(|e| ApiError::storage("password resets", e))?;
+    }
+
+    let message = format!(
+        "Hello {},\n\nSomeone asked to reset your course scheduler password. Use this code to choose a new one:\n\n{}\n\n\
+         The code works once and expires in {} minutes. If you didn't ask for it, you can ignore this email.\n",
+        body.username, token, RESET_MINUTES
+    );
+    if let Err(e) = send_email(&state, &email, "Reset your course scheduler password", &message) {
+        println!("Failed to build password reset email for {}: {}", body.username, e);
+    }
+    // Whoever asked isn't logged in, so they can't be named
+    audit::record(&state, "anonymous", "user.password_reset_request", format!("user:{}", body.username), None::<&User>, None::<&User>);
+    Ok(sent)
+}
+
+// POST /api/password/reset/confirm
+#[utoipa::path(
+    post,
+    path = "/api/password/reset/confirm",
+    tag = "auth",
+    request_body = ResetConfirm,
+    responses(
+        (status = 200, description = "Password reset, the user can now log in with it"),
+        (status = 400, description = "The code is unknown, used or expired, or the new password is too weak", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn confirm_password_reset(body: web::Json<ResetConfirm>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let token_hash = hash_token(body.token.trim());
+    let username = {
+        let mut resets = state.password_resets.lock().unwrap();
+        let reset = resets
+            .iter()
+            .find(|r| r.token_hash == token_hash && r.expires_at > Utc::now())
+            .ok_or(ApiError::InvalidToken)?;
+        validate_password_strength("new_password", &reset.username, &body.new_password)?;
+
+        // Use up the code before anything else can
+        let username = reset.username.clone();
+        resets.retain(|r| r.username != username);
+        save_password_resets(&resets).map_err(|e| ApiError::storage("password resets", e))?;
+        username
+    };
+
+    set_password(&state, &username, "user.password_reset", &username, body.new_password)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Password reset"})))
+}
@@ -0,0 +1,231 @@
+// Bulk creation of student accounts from a roster CSV. Each new student gets
+// either a generated one-time password, which must be changed at first login,
+// or an activation token they redeem to choose their own password. Usernames
+// that already exist are reported and left alone.
+use std::collections::HashSet;
+
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::audit;
+use super::auth::{AuthUser, Role};
+use super::error::{ApiError, ApiErrorBody};
+use super::import::{read_body, read_csv, RowError};
+use super::passwords::{hash_token, validate_password_strength};
+use super::share::new_token;
+use super::{save_users, validate_email, AppState, User};
+
+// How long an activation token stays valid
+const ACTIVATION_DAYS: i64 = 14;
+
+// Length of a generated initial password
+const PASSWORD_LENGTH: usize = 12;
+
+// Characters generated passwords are made of, without ones that are easy to misread like 0/O and 1/l
+const PASSWORD_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+// Most rows in one roster
+const MAX_ROWS: usize = 5000;
+
+// What provisioned students get to sign in with
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialKind {
+    // A generated password, to be replaced at first login
+    #[default]
+    Password,
+    // A token the student redeems at POST /api/activate to set their own password
+    Activation,
+}
+
+// How to provision a roster. The CSV needs a `username` column and may have an `email` column.
+#[derive(Deserialize, Debug, Default, IntoParams)]
+pub struct ProvisionOptions {
+    #[serde(default)]
+    credentials: CredentialKind,
+    // Check the roster and report problems without creating anyone
+    #[serde(default)]
+    dry_run: bool,
+}
+
+// A pending account activation
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct Activation {
+    // Only the hash is kept, the token itself is shown once in the provisioning response
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+// Credentials of a newly created student, shown only in the provisioning response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ProvisionedUser {
+    username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    // Generated initial password
+    #[serde(skip_serializing_if = "Option::is_none")]
+    password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    activation_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// Outcome of provisioning a roster or a dry run
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ProvisionReport {
+    dry_run: bool,
+    // Roster rows read, not counting blank ones
+    rows: usize,
+    // Accounts created, empty on a dry run
+    created: Vec<ProvisionedUser>,
+    // Rows whose username is already taken, which are skipped
+    duplicates: Vec<RowError>,
+    errors: Vec<RowError>,
+}
+
+// Redeems an activation token
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct ActivateRequest {
+    token: String,
+    password: String,
+}
+
+// Makes a random initial password
+fn generate_password() -> String {
+    (0..PASSWORD_LENGTH)
+        .map(|_| PASSWORD_ALPHABET[rand::random_range(0..PASSWORD_ALPHABET.len())] as char)
+        .collect()
+}
+
+// POST /api/users/import
+#[utoipa::path(
+    post,
+    path = "/api/users/import",
+    tag = "users",
+    params(ProvisionOptions),
+    request_body(content = String, description = "Roster CSV with a `username` column and an optional `email` column", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Students created with their credentials, or the problems found on a dry run", body = ProvisionReport),
+        (status = 400, description = "Unreadable file, missing columns, or rows that failed validation", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn provision_users(admin: AuthUser, options: web::Query<ProvisionOptions>, payload: web::Payload, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let table = read_csv(&read_body(payload).await?)?;
+    let column = |name: &str| table.headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let username_column = column("username").ok_or_else(|| {
+        ApiError::InvalidRequest(format!("No column named 'username'; columns are: {}", table.headers.join(", ")))
+    })?;
+    let email_column = column("email");
+
+    let rows: Vec<&(usize, Vec<String>)> = table.rows.iter().filter(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty())).collect();
+    if rows.is_empty() {
+        return Err(ApiError::InvalidRequest("The roster has no students".to_string()));
+    }
+    if rows.len() > MAX_ROWS {
+        return Err(ApiError::InvalidRequest(format!("Too many rows, the limit is {}", MAX_ROWS)));
+    }
+
+    let mut users = state.users.lock().unwrap();
+    let mut seen = HashSet::new();
+    let mut accepted = Vec::new();
+    let mut duplicates = Vec::new();
+    let mut errors = Vec::new();
+    for (row, cells) in &rows {
+        let cell = |index: Option<usize>| index.and_then(|i| cells.get(i)).map(|v| v.trim().to_string()).unwrap_or_default();
+        let username = cell(Some(username_column));
+        let email = Some(cell(email_column)).filter(|e| !e.is_empty());
+
+        if username.is_empty() {
+            errors.push(RowError::new(*row, Some("username".to_string()), "Username is required".to_string()));
+        } else if username.chars().any(|c| c.is_whitespace() || c == '/') {
+            errors.push(RowError::new(*row, Some("username".to_string()), "Username cannot contain spaces or '/'".to_string()));
+        } else if let Some(Err(e)) = email.as_deref().map(validate_email) {
+            errors.push(RowError::new(*row, Some("email".to_string()), e.to_string()));
+        } else if users.iter().any(|u| u.username == username) {
+            duplicates.push(RowError::new(*row, Some("username".to_string()), format!("'{}' already has an account", username)));
+        } else if !seen.insert(username.clone()) {
+            errors.push(RowError::new(*row, Some("username".to_string()), format!("'{}' is listed more than once", username)));
+        } else {
+            accepted.push((username, email));
+        }
+    }
+
+    if options.dry_run {
+        return Ok(HttpResponse::Ok().json(ProvisionReport { dry_run: true, rows: rows.len(), created: Vec::new(), duplicates, errors }));
+    }
+    if !errors.is_empty() {
+        return Err(ApiError::ImportFailed(errors));
+    }
+
+    let expires_at = Utc::now() + Duration::days(ACTIVATION_DAYS);
+    let mut created = Vec::new();
+    for (username, email) in accepted {
+        let (password, token) = match options.credentials {
+            CredentialKind::Password => (generate_password(), None),
+            CredentialKind::Activation => (String::new(), Some(new_token())),
+        };
+        let activation = token.as_deref().map(|t| Activation { token_hash: hash_token(t), expires_at });
+        users.push(User {
+            username: username.clone(),
+            password: password.clone(),
+            role: Role::Student,
+            instructor_name: None,
+            advisor: None,
+            email: email.clone(),
+            email_notifications: Vec::new(),
+            must_change_password: activation.is_none(),
+            activation: activation.clone(),
+        });
+        created.push(ProvisionedUser {
+            username,
+            email,
+            password: activation.is_none().then_some(password),
+            activation_token: token,
+            expires_at: activation.map(|a| a.expires_at),
+        });
+    }
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+
+    let new_users: Vec<&User> = users.iter().filter(|u| created.iter().any(|c| c.username == u.username)).collect();
+    for user in new_users {
+        audit::record(&state, &admin.username, "user.create", format!("user:{}", user.username), None::<&User>, Some(user));
+    }
+    Ok(HttpResponse::Ok().json(ProvisionReport { dry_run: false, rows: rows.len(), created, duplicates, errors }))
+}
+
+// POST /api/activate
+#[utoipa::path(
+    post,
+    path = "/api/activate",
+    tag = "auth",
+    request_body = ActivateRequest,
+    responses(
+        (status = 200, description = "Account activated, the user can now log in"),
+        (status = 400, description = "The token is unknown or expired, or the password is too weak", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn activate_account(body: web::Json<ActivateRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let token_hash = hash_token(body.token.trim());
+    let mut users = state.users.lock().unwrap();
+    let user = users
+        .iter_mut()
+        .find(|u| u.activation.as_ref().is_some_and(|a| a.token_hash == token_hash && a.expires_at > Utc::now()))
+        .ok_or(ApiError::InvalidToken)?;
+    validate_password_strength("password", &user.username, &body.password)?;
+    let before = user.clone();
+    user.password = body.password;
+    user.activation = None;
+    user.must_change_password = false;
+    let after = user.clone();
+
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    audit::record(&state, &after.username, "user.activate", format!("user:{}", after.username), Some(&before), Some(&after));
+    Ok(HttpResponse::Ok().json(serde_json::json!({"message": "Account activated"})))
+}
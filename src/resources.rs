@@ -0,0 +1,396 @@
+// Resource-style routes for courses, users and the logged-in student's schedule.
+// These share their logic with the older RPC-style routes in main.rs but use
+// proper HTTP verbs and status codes (201 on create, 204 on delete, 404 when missing).
+use actix_web::{web, HttpResponse, Responder};
+
+use super::auth::{AuthUser, Role};
+use super::error::{ApiError, ApiErrorBody};
+use super::{
+    create_account, create_course, edit_schedule_entry, list_accounts, preview_course_change, remove_course,
+    remove_user, replace_course, schedule_course, schedule_of, scheduled_courses, swap_scheduled_course,
+    unschedule_course, upsert_student, AppState, Course, CourseChange, CourseChangeOptions, CourseImpact, CoursePatch,
+    NewCourse, Schedule, SchedulePatch, ScheduledCourse, SwapRequest, User, UserPassword, UserPatch,
+};
+
+// GET /api/courses/{id}
+#[utoipa::path(
+    get,
+    path = "/api/courses/{id}",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "The course", body = Course),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+    )
+)]
+pub async fn get_course(id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let courses = state.courses.lock().unwrap();
+    let course = courses.iter().find(|c| c.id == *id).ok_or(ApiError::CourseNotFound)?;
+    Ok(HttpResponse::Ok().json(course))
+}
+
+// POST /api/courses
+#[utoipa::path(
+    post,
+    path = "/api/courses",
+    tag = "courses",
+    request_body = NewCourse,
+    responses(
+        (status = 201, description = "Course created", body = Course),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn post_course(admin: AuthUser, course: web::Json<NewCourse>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let course = create_course(&state, &admin.username, course.into_inner())?;
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/api/courses/{}", course.id)))
+        .json(course))
+}
+
+// PUT /api/courses/{id}
+#[utoipa::path(
+    put,
+    path = "/api/courses/{id}",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID"), CourseChangeOptions),
+    request_body = NewCourse,
+    responses(
+        (status = 200, description = "Course replaced, or the impact of replacing it on a dry run", body = CourseChange),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn put_course(admin: AuthUser, id: web::Path<u32>, options: web::Query<CourseChangeOptions>, course: web::Json<NewCourse>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if options.dry_run {
+        let impact = preview_course_change(&state, id.into_inner(), Some(course.into_inner()))?;
+        return Ok(HttpResponse::Ok().json(CourseChange::DryRun(impact)));
+    }
+    let course = replace_course(&state, &admin.username, id.into_inner(), course.into_inner(), options.keep_enrolled)?;
+    Ok(HttpResponse::Ok().json(CourseChange::Saved(course)))
+}
+
+// PATCH /api/courses/{id}
+#[utoipa::path(
+    patch,
+    path = "/api/courses/{id}",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID"), CourseChangeOptions),
+    request_body = CoursePatch,
+    responses(
+        (status = 200, description = "Course updated, or the impact of updating it on a dry run", body = CourseChange),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn patch_course(admin: AuthUser, id: web::Path<u32>, options: web::Query<CourseChangeOptions>, patch: web::Json<CoursePatch>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let patch = patch.into_inner();
+    let existing = state.courses.lock().unwrap().iter().find(|c| c.id == id).cloned();
+    let existing = existing.ok_or(ApiError::CourseNotFound)?;
+
+    let merged = NewCourse {
+        dept_code: patch.dept_code.unwrap_or(existing.dept_code),
+        course_number: patch.course_number.unwrap_or(existing.course_number),
+        instructor: patch.instructor.unwrap_or(existing.instructor),
+        description: patch.description.unwrap_or(existing.description),
+        location: patch.location.unwrap_or(existing.location),
+        meeting_time: patch.meeting_time.unwrap_or(existing.meeting_time),
+    };
+
+    if options.dry_run {
+        return Ok(HttpResponse::Ok().json(CourseChange::DryRun(preview_course_change(&state, id, Some(merged))?)));
+    }
+    let course = replace_course(&state, &admin.username, id, merged, options.keep_enrolled)?;
+    Ok(HttpResponse::Ok().json(CourseChange::Saved(course)))
+}
+
+// DELETE /api/courses/{id}
+#[utoipa::path(
+    delete,
+    path = "/api/courses/{id}",
+    tag = "courses",
+    params(("id" = u32, Path, description = "Course ID"), CourseChangeOptions),
+    responses(
+        (status = 200, description = "Impact of deleting the course, on a dry run", body = CourseImpact),
+        (status = 204, description = "Course deleted"),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn delete_course(admin: AuthUser, id: web::Path<u32>, options: web::Query<CourseChangeOptions>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if options.dry_run {
+        return Ok(HttpResponse::Ok().json(preview_course_change(&state, id.into_inner(), None)?));
+    }
+    remove_course(&state, &admin.username, id.into_inner())?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/users
+#[utoipa::path(
+    get,
+    path = "/api/users",
+    tag = "users",
+    responses(
+        (status = 200, description = "Student and instructor accounts", body = Vec<User>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_users(state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(list_accounts(&state))
+}
+
+// POST /api/users
+#[utoipa::path(
+    post,
+    path = "/api/users",
+    tag = "users",
+    request_body = User,
+    responses(
+        (status = 201, description = "Student or instructor created", body = User),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn post_user(admin: AuthUser, user: web::Json<User>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let user = create_account(&state, &admin.username, user.into_inner())?;
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/api/users/{}", user.username)))
+        .json(user))
+}
+
+// GET /api/users/{username}
+#[utoipa::path(
+    get,
+    path = "/api/users/{username}",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    responses(
+        (status = 200, description = "The account", body = User),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+    )
+)]
+pub async fn get_user(username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let users = state.users.lock().unwrap();
+    let user = users.iter().find(|u| u.username == *username).ok_or(ApiError::UserNotFound)?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+// PUT /api/users/{username}
+#[utoipa::path(
+    put,
+    path = "/api/users/{username}",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    request_body = UserPassword,
+    responses(
+        (status = 200, description = "Password replaced", body = User),
+        (status = 201, description = "Student created", body = User),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn put_user(admin: AuthUser, username: web::Path<String>, body: web::Json<UserPassword>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (user, created) = upsert_student(&state, &admin.username, username.into_inner(), body.into_inner().password, true)?;
+    if created {
+        Ok(HttpResponse::Created()
+            .insert_header(("Location", format!("/api/users/{}", user.username)))
+            .json(user))
+    } else {
+        Ok(HttpResponse::Ok().json(user))
+    }
+}
+
+// PATCH /api/users/{username}
+#[utoipa::path(
+    patch,
+    path = "/api/users/{username}",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    request_body = UserPatch,
+    responses(
+        (status = 200, description = "Student updated", body = User),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn patch_user(admin: AuthUser, username: web::Path<String>, patch: web::Json<UserPatch>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let username = username.into_inner();
+    let current = state.users.lock().unwrap().iter().find(|u| u.username == username).cloned();
+    let current = current.ok_or(ApiError::UserNotFound)?;
+
+    if current.role == Role::Admin {
+        return Err(ApiError::ProtectedAccount("Cannot modify admin account".to_string()));
+    }
+
+    // Without a new password there is nothing to change, and a pending activation stays pending
+    let Some(password) = patch.into_inner().password else {
+        return Ok(HttpResponse::Ok().json(current));
+    };
+
+    let (user, _) = upsert_student(&state, &admin.username, username, password, true)?;
+    Ok(HttpResponse::Ok().json(user))
+}
+
+// DELETE /api/users/{username}
+#[utoipa::path(
+    delete,
+    path = "/api/users/{username}",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    responses(
+        (status = 204, description = "Account deleted"),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn delete_user(admin: AuthUser, username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    remove_user(&state, &admin.username, &username.into_inner())?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/me/schedule
+#[utoipa::path(
+    get,
+    path = "/api/me/schedule",
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Scheduled courses with their catalog entries", body = Vec<(ScheduledCourse, Course)>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn get_my_schedule(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok().json(scheduled_courses(&state, &user.username))
+}
+
+// GET /api/me/schedule/{course_id}
+#[utoipa::path(
+    get,
+    path = "/api/me/schedule/{course_id}",
+    tag = "schedule",
+    params(("course_id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "The entry with its catalog course", body = (ScheduledCourse, Course)),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Schedule entry not found", body = ApiErrorBody),
+    )
+)]
+pub async fn get_my_schedule_entry(user: AuthUser, course_id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let entry = scheduled_courses(&state, &user.username)
+        .into_iter()
+        .find(|(sc, _)| sc.course_id == *course_id)
+        .ok_or(ApiError::NotInSchedule)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+// PUT /api/me/schedule/{course_id}
+#[utoipa::path(
+    put,
+    path = "/api/me/schedule/{course_id}",
+    tag = "schedule",
+    params(("course_id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 200, description = "Already scheduled", body = ScheduledCourse),
+        (status = 201, description = "Added to schedule", body = ScheduledCourse),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 409, description = "Time conflict with a scheduled course", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn put_my_schedule_entry(user: AuthUser, course_id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let course_id = course_id.into_inner();
+    let (schedule, added) = schedule_course(&state, &user.username, course_id)?;
+    let entry = schedule.courses.into_iter().find(|c| c.course_id == course_id);
+    if added {
+        Ok(HttpResponse::Created()
+            .insert_header(("Location", format!("/api/me/schedule/{}", course_id)))
+            .json(entry))
+    } else {
+        Ok(HttpResponse::Ok().json(entry))
+    }
+}
+
+// PATCH /api/me/schedule/{course_id}
+#[utoipa::path(
+    patch,
+    path = "/api/me/schedule/{course_id}",
+    tag = "schedule",
+    params(("course_id" = u32, Path, description = "Course ID")),
+    request_body = SchedulePatch,
+    responses(
+        (status = 200, description = "Entry updated", body = ScheduledCourse),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Schedule entry not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn patch_my_schedule_entry(user: AuthUser, course_id: web::Path<u32>, patch: web::Json<SchedulePatch>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let course_id = course_id.into_inner();
+    let patch = patch.into_inner();
+    let current = schedule_of(&state, &user.username).courses.into_iter().find(|c| c.course_id == course_id);
+    let current = current.ok_or(ApiError::NotInSchedule)?;
+
+    let entry = ScheduledCourse {
+        course_id,
+        notes: patch.notes.unwrap_or(current.notes),
+        slot: patch.slot.unwrap_or(current.slot),
+    };
+
+    let entry = edit_schedule_entry(&state, &user.username, entry)?;
+    Ok(HttpResponse::Ok().json(entry))
+}
+
+// DELETE /api/me/schedule/{course_id}
+#[utoipa::path(
+    delete,
+    path = "/api/me/schedule/{course_id}",
+    tag = "schedule",
+    params(("course_id" = u32, Path, description = "Course ID")),
+    responses(
+        (status = 204, description = "Dropped from schedule"),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Schedule entry not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn delete_my_schedule_entry(user: AuthUser, course_id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (_, removed) = unschedule_course(&state, &user.username, course_id.into_inner())?;
+    if !removed {
+        return Err(ApiError::NotInSchedule);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// POST /api/me/schedule/swap
+#[utoipa::path(
+    post,
+    path = "/api/me/schedule/swap",
+    tag = "schedule",
+    request_body = SwapRequest,
+    responses(
+        (status = 200, description = "Updated schedule", body = Schedule),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Course not found", body = ApiErrorBody),
+        (status = 409, description = "Time conflict with a scheduled course", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn swap_my_schedule(user: AuthUser, swap: web::Json<SwapRequest>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let schedule = swap_scheduled_course(&state, &user.username, swap.into_inner())?;
+    Ok(HttpResponse::Ok().json(schedule))
+}
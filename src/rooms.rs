@@ -0,0 +1,460 @@
+// Collaborative planning rooms. A student creates a room holding a shared
+// draft schedule and invites other students; members connect to the room's
+// WebSocket and every add, drop or note edit is checked for conflicts on the
+// server and then sent to everyone in the room.
+use std::collections::HashMap;
+use std::fs;
+
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::StreamExt;
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::{AuthUser, Role};
+use super::error::{ApiError, ApiErrorBody};
+use super::{alternative_sections, schedule_conflicts, AppState, ConflictDetails, Schedule, ScheduledCourse};
+
+// Room events buffered per connection before a slow client gets a fresh snapshot instead
+const CHANNEL_CAPACITY: usize = 64;
+
+// Open event channel of each room with connected members
+pub type RoomChannels = HashMap<u32, broadcast::Sender<RoomEvent>>;
+
+// A shared draft schedule and the students allowed to edit it
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct PlanningRoom {
+    id: u32,
+    name: String,
+    owner: String,
+    members: Vec<String>,
+    courses: Vec<ScheduledCourse>,
+}
+
+// Data for creating a room
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct NewRoom {
+    name: String,
+}
+
+// An edit a member sends over the WebSocket
+#[derive(Deserialize, Debug, ToSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RoomAction {
+    Add { course_id: u32 },
+    Drop { course_id: u32 },
+    Note { course_id: u32, notes: String },
+}
+
+// A message sent to members over the WebSocket
+#[derive(Serialize, Clone, Debug, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RoomEvent {
+    // The whole room, sent on connect and after missed events
+    Snapshot { room: PlanningRoom },
+    Joined { username: String },
+    Left { username: String },
+    Removed { username: String },
+    Added { by: String, entry: ScheduledCourse },
+    Dropped { by: String, course_id: u32 },
+    Noted { by: String, entry: ScheduledCourse },
+    // Only sent to the member whose edit was refused
+    Error { error: ApiErrorBody },
+}
+
+// Loads planning rooms from file
+pub fn load_rooms() -> Vec<PlanningRoom> {
+    fs::read_to_string("planning_rooms.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves planning rooms
+fn save_rooms(rooms: &Vec<PlanningRoom>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(rooms)?;
+    fs::write("planning_rooms.json", data)?;
+    Ok(())
+}
+
+// Returns a room if the user is one of its members
+fn member_room(state: &AppState, id: u32, username: &str) -> Result<PlanningRoom, ApiError> {
+    let rooms = state.rooms.lock().unwrap();
+    let room = rooms.iter().find(|r| r.id == id).ok_or(ApiError::RoomNotFound)?;
+    if !room.members.iter().any(|m| m == username) {
+        return Err(ApiError::Forbidden("You are not a member of this room".to_string()));
+    }
+    Ok(room.clone())
+}
+
+// Returns the index of a room if the user owns it
+fn owned_room(rooms: &[PlanningRoom], id: u32, username: &str) -> Result<usize, ApiError> {
+    let index = rooms.iter().position(|r| r.id == id).ok_or(ApiError::RoomNotFound)?;
+    if rooms[index].owner != username {
+        return Err(ApiError::Forbidden("Only the room's owner can do this".to_string()));
+    }
+    Ok(index)
+}
+
+// Sends an event to every connection in a room
+fn broadcast(state: &AppState, id: u32, event: RoomEvent) {
+    if let Some(sender) = state.room_channels.lock().unwrap().get(&id) {
+        // Sending only fails when nobody is connected
+        let _ = sender.send(event);
+    }
+}
+
+// Subscribes to a room's events, opening its channel on first use
+fn subscribe(state: &AppState, id: u32) -> broadcast::Receiver<RoomEvent> {
+    let mut channels = state.room_channels.lock().unwrap();
+    channels.entry(id).or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0).subscribe()
+}
+
+// Applies one member's edit to the shared draft, refusing adds that would conflict
+fn apply_action(state: &AppState, id: u32, username: &str, action: RoomAction) -> Result<RoomEvent, ApiError> {
+    let courses = state.courses.lock().unwrap();
+    let mut rooms = state.rooms.lock().unwrap();
+    let room = rooms.iter_mut().find(|r| r.id == id).ok_or(ApiError::RoomNotFound)?;
+    if !room.members.iter().any(|m| m == username) {
+        return Err(ApiError::Forbidden("You are not a member of this room".to_string()));
+    }
+
+    let by = username.to_string();
+    let event = match action {
+        RoomAction::Add { course_id } => {
+            let course = courses.iter().find(|c| c.id == course_id).ok_or(ApiError::CourseNotFound)?;
+            if room.courses.iter().any(|c| c.course_id == course_id) {
+                return Err(ApiError::AlreadyInSchedule);
+            }
+            let draft = Schedule { courses: room.courses.clone(), ..Schedule::default() };
+            let conflicts = schedule_conflicts(course, &draft, &courses);
+            if !conflicts.is_empty() {
+                return Err(ApiError::TimeConflict(ConflictDetails {
+                    conflicts,
+                    alternatives: alternative_sections(course, &draft, &courses),
+                }));
+            }
+            let entry = ScheduledCourse { course_id, notes: String::new(), slot: String::new() };
+            room.courses.push(entry.clone());
+            RoomEvent::Added { by, entry }
+        }
+        RoomAction::Drop { course_id } => {
+            let index = room.courses.iter().position(|c| c.course_id == course_id).ok_or(ApiError::NotInSchedule)?;
+            room.courses.remove(index);
+            RoomEvent::Dropped { by, course_id }
+        }
+        RoomAction::Note { course_id, notes } => {
+            let entry = room.courses.iter_mut().find(|c| c.course_id == course_id).ok_or(ApiError::NotInSchedule)?;
+            entry.notes = notes;
+            RoomEvent::Noted { by, entry: entry.clone() }
+        }
+    };
+
+    save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+    Ok(event)
+}
+
+// Removes a deleted account from every room, deleting the rooms it owned
+pub fn forget_user(state: &AppState, username: &str) -> Result<(), ApiError> {
+    let mut rooms = state.rooms.lock().unwrap();
+    let owned: Vec<u32> = rooms.iter().filter(|r| r.owner == username).map(|r| r.id).collect();
+    let count = rooms.len();
+    rooms.retain(|r| r.owner != username);
+    let mut changed = rooms.len() != count;
+    for room in rooms.iter_mut() {
+        if let Some(index) = room.members.iter().position(|m| m == username) {
+            room.members.remove(index);
+            changed = true;
+            broadcast(state, room.id, RoomEvent::Removed { username: username.to_string() });
+        }
+    }
+    if changed {
+        save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+    }
+    drop(rooms);
+
+    // Dropping a room's channel closes its open connections
+    let mut channels = state.room_channels.lock().unwrap();
+    for id in owned {
+        channels.remove(&id);
+    }
+    Ok(())
+}
+
+// Sends one event to a single connection
+async fn send(session: &mut Session, event: &RoomEvent) -> Result<(), actix_ws::Closed> {
+    session.text(serde_json::to_string(event).unwrap_or_default()).await
+}
+
+// Whether the member who opened a connection is still the one logged in
+fn still_logged_in(state: &AppState, username: &str) -> bool {
+    state.session.lock().unwrap().as_ref().is_some_and(|s| s.username == username)
+}
+
+// Relays a member's edits into the room and the room's events back to them until either side closes
+async fn run_socket(
+    state: web::Data<AppState>,
+    room: PlanningRoom,
+    username: String,
+    mut session: Session,
+    mut stream: MessageStream,
+    mut events: broadcast::Receiver<RoomEvent>,
+) {
+    let id = room.id;
+    if send(&mut session, &RoomEvent::Snapshot { room }).await.is_err() {
+        return;
+    }
+    broadcast(&state, id, RoomEvent::Joined { username: username.clone() });
+
+    // The connection only acts for the member while their session lasts, so a logout or another login closes it
+    let logged_out = || CloseReason { code: CloseCode::Policy, description: Some("Logged out".to_string()) };
+    let mut close_reason = None;
+    loop {
+        tokio::select! {
+            message = stream.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if !still_logged_in(&state, &username) {
+                        close_reason = Some(logged_out());
+                        break;
+                    }
+                    let result = serde_json::from_str::<RoomAction>(&text)
+                        .map_err(|e| ApiError::InvalidRequest(e.to_string()))
+                        .and_then(|action| apply_action(&state, id, &username, action));
+                    match result {
+                        Ok(event) => broadcast(&state, id, event),
+                        Err(e) => {
+                            if send(&mut session, &RoomEvent::Error { error: e.body() }).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if session.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    // Too far behind to replay, so start the client over from the current state
+                    Err(RecvError::Lagged(_)) => match member_room(&state, id, &username) {
+                        Ok(room) => RoomEvent::Snapshot { room },
+                        Err(_) => break,
+                    },
+                    // The room was deleted
+                    Err(RecvError::Closed) => break,
+                };
+                if !still_logged_in(&state, &username) {
+                    close_reason = Some(logged_out());
+                    break;
+                }
+                let removed = matches!(&event, RoomEvent::Removed { username: who } if *who == username);
+                if send(&mut session, &event).await.is_err() || removed {
+                    break;
+                }
+            }
+        }
+    }
+
+    broadcast(&state, id, RoomEvent::Left { username });
+    let _ = session.close(close_reason).await;
+}
+
+// GET /api/rooms
+#[utoipa::path(
+    get,
+    path = "/api/rooms",
+    tag = "rooms",
+    responses(
+        (status = 200, description = "Planning rooms the logged-in student belongs to", body = Vec<PlanningRoom>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_rooms(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    let rooms = state.rooms.lock().unwrap();
+    let mine: Vec<&PlanningRoom> = rooms.iter().filter(|r| r.members.contains(&user.username)).collect();
+    HttpResponse::Ok().json(mine)
+}
+
+// POST /api/rooms
+#[utoipa::path(
+    post,
+    path = "/api/rooms",
+    tag = "rooms",
+    request_body = NewRoom,
+    responses(
+        (status = 201, description = "Room created with the logged-in student as owner", body = PlanningRoom),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn create_room(user: AuthUser, body: web::Json<NewRoom>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let name = body.into_inner().name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::validation("name", "Give the room a name"));
+    }
+
+    let mut rooms = state.rooms.lock().unwrap();
+    let room = PlanningRoom {
+        id: rooms.iter().map(|r| r.id).max().unwrap_or(0) + 1,
+        name,
+        owner: user.username.clone(),
+        members: vec![user.username.clone()],
+        courses: Vec::new(),
+    };
+    rooms.push(room.clone());
+    save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+    audit::record(&state, &user.username, "room.create", format!("room:{}", room.id), None::<&PlanningRoom>, Some(&room));
+    Ok(HttpResponse::Created().json(room))
+}
+
+// GET /api/rooms/{id}
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{id}",
+    tag = "rooms",
+    params(("id" = u32, Path, description = "Room ID")),
+    responses(
+        (status = 200, description = "The room and its draft schedule", body = PlanningRoom),
+        (status = 403, description = "Not a member of the room", body = ApiErrorBody),
+        (status = 404, description = "Room not found", body = ApiErrorBody),
+    )
+)]
+pub async fn get_room(user: AuthUser, id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(member_room(&state, *id, &user.username)?))
+}
+
+// DELETE /api/rooms/{id}
+#[utoipa::path(
+    delete,
+    path = "/api/rooms/{id}",
+    tag = "rooms",
+    params(("id" = u32, Path, description = "Room ID")),
+    responses(
+        (status = 204, description = "Room deleted and its connections closed"),
+        (status = 403, description = "Not the room's owner", body = ApiErrorBody),
+        (status = 404, description = "Room not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn delete_room(user: AuthUser, id: web::Path<u32>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    let mut rooms = state.rooms.lock().unwrap();
+    let index = owned_room(&rooms, id, &user.username)?;
+    let room = rooms.remove(index);
+    save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+    drop(rooms);
+
+    state.room_channels.lock().unwrap().remove(&id);
+    audit::record(&state, &user.username, "room.delete", format!("room:{}", id), Some(&room), None::<&PlanningRoom>);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// PUT /api/rooms/{id}/members/{username}
+#[utoipa::path(
+    put,
+    path = "/api/rooms/{id}/members/{username}",
+    tag = "rooms",
+    params(
+        ("id" = u32, Path, description = "Room ID"),
+        ("username" = String, Path, description = "Student to add"),
+    ),
+    responses(
+        (status = 200, description = "Student is a member of the room", body = PlanningRoom),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not the room's owner", body = ApiErrorBody),
+        (status = 404, description = "Room not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn add_member(user: AuthUser, path: web::Path<(u32, String)>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (id, member) = path.into_inner();
+    let is_student = state.users.lock().unwrap().iter().any(|u| u.username == member && u.role == Role::Student);
+    if !is_student {
+        return Err(ApiError::validation("username", "No student with that username"));
+    }
+
+    let mut rooms = state.rooms.lock().unwrap();
+    let index = owned_room(&rooms, id, &user.username)?;
+    if !rooms[index].members.contains(&member) {
+        let before = rooms[index].clone();
+        rooms[index].members.push(member.clone());
+        save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+        audit::record(&state, &user.username, "room.add_member", format!("room:{}", id), Some(&before), Some(&rooms[index]));
+    }
+    Ok(HttpResponse::Ok().json(&rooms[index]))
+}
+
+// DELETE /api/rooms/{id}/members/{username}
+#[utoipa::path(
+    delete,
+    path = "/api/rooms/{id}/members/{username}",
+    tag = "rooms",
+    params(
+        ("id" = u32, Path, description = "Room ID"),
+        ("username" = String, Path, description = "Member to remove, or yourself to leave"),
+    ),
+    responses(
+        (status = 204, description = "Member removed"),
+        (status = 400, description = "The owner cannot leave their own room", body = ApiErrorBody),
+        (status = 403, description = "Not allowed to remove this member", body = ApiErrorBody),
+        (status = 404, description = "Room or member not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn remove_member(user: AuthUser, path: web::Path<(u32, String)>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let (id, member) = path.into_inner();
+    let mut rooms = state.rooms.lock().unwrap();
+    let room = rooms.iter_mut().find(|r| r.id == id).ok_or(ApiError::RoomNotFound)?;
+    if room.owner != user.username && member != user.username {
+        return Err(ApiError::Forbidden("Only the room's owner can remove other members".to_string()));
+    }
+    if member == room.owner {
+        return Err(ApiError::InvalidRequest("The owner cannot leave the room, delete it instead".to_string()));
+    }
+    let index = room.members.iter().position(|m| *m == member).ok_or(ApiError::UserNotFound)?;
+
+    let before = room.clone();
+    room.members.remove(index);
+    let after = room.clone();
+    save_rooms(&rooms).map_err(|e| ApiError::storage("planning rooms", e))?;
+    drop(rooms);
+
+    broadcast(&state, id, RoomEvent::Removed { username: member });
+    audit::record(&state, &user.username, "room.remove_member", format!("room:{}", id), Some(&before), Some(&after));
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/rooms/{id}/ws
+#[utoipa::path(
+    get,
+    path = "/api/rooms/{id}/ws",
+    tag = "rooms",
+    params(("id" = u32, Path, description = "Room ID")),
+    responses(
+        (status = 101, description = "WebSocket opened. Send `RoomAction` messages and receive `RoomEvent` messages as JSON text.", body = RoomEvent),
+        (status = 400, description = "Not a WebSocket upgrade request", body = ApiErrorBody),
+        (status = 403, description = "Not a member of the room", body = ApiErrorBody),
+        (status = 404, description = "Room not found", body = ApiErrorBody),
+    )
+)]
+pub async fn room_socket(
+    user: AuthUser,
+    id: web::Path<u32>,
+    req: HttpRequest,
+    body: web::Payload,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let room = member_room(&state, *id, &user.username)?;
+    let (response, session, stream) = actix_ws::handle(&req, body)?;
+    let events = subscribe(&state, room.id);
+    actix_web::rt::spawn(run_socket(state, room, user.username, session, stream, events));
+    Ok(response)
+}
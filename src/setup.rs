@@ -0,0 +1,113 @@
+// First-run setup. There is no built-in admin account: while no admin exists,
+// the server prints a one-time setup token at startup, and whoever runs it
+// uses the token to create the first admin with a password of their choosing.
+// The token only lives in memory and stops working once the admin is created.
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::Role;
+use super::error::{ApiError, ApiErrorBody};
+use super::passwords::validate_password_strength;
+use super::share::new_token;
+use super::{save_users, AppState, User};
+
+// Whether the first admin still has to be created
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SetupStatus {
+    required: bool,
+}
+
+// The first admin account, created with the token printed at startup
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct InitialAdmin {
+    token: String,
+    username: String,
+    password: String,
+}
+
+// Makes a setup token when there is no admin account yet and tells the operator about it
+pub fn setup_token(users: &[User]) -> Option<String> {
+    if users.iter().any(|u| u.role == Role::Admin) {
+        return None;
+    }
+    let token = new_token();
+    println!("No admin account exists yet. Create one at http://localhost:8080 with this one-time setup token:");
+    println!("    {}", token);
+    Some(token)
+}
+
+// GET /api/setup
+#[utoipa::path(
+    get,
+    path = "/api/setup",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Whether the first admin account still has to be created", body = SetupStatus),
+    )
+)]
+pub async fn setup_status(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(SetupStatus { required: state.setup_token.lock().unwrap().is_some() })
+}
+
+// POST /api/setup
+#[utoipa::path(
+    post,
+    path = "/api/setup",
+    tag = "auth",
+    request_body = InitialAdmin,
+    responses(
+        (status = 201, description = "Admin account created, it can now log in"),
+        (status = 400, description = "Wrong setup token, invalid username, or the password is too weak", body = ApiErrorBody),
+        (status = 403, description = "Setup is already done", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn create_initial_admin(body: web::Json<InitialAdmin>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    let mut setup_token = state.setup_token.lock().unwrap();
+    let Some(token) = setup_token.as_ref() else {
+        return Err(ApiError::Forbidden("Setup is already done".to_string()));
+    };
+    if body.token.trim() != token {
+        return Err(ApiError::InvalidToken);
+    }
+
+    let username = body.username.trim().to_string();
+    if username.is_empty() {
+        return Err(ApiError::validation("username", "Username is required"));
+    }
+    if username.chars().any(|c| c.is_whitespace() || c == '/') {
+        return Err(ApiError::validation("username", "Username cannot contain spaces or '/'"));
+    }
+    validate_password_strength("password", &username, &body.password)?;
+
+    let mut users = state.users.lock().unwrap();
+    if users.iter().any(|u| u.username == username) {
+        return Err(ApiError::UsernameTaken);
+    }
+    let admin = User {
+        username: username.clone(),
+        password: body.password,
+        role: Role::Admin,
+        instructor_name: None,
+        advisor: None,
+        email: None,
+        email_notifications: Vec::new(),
+        must_change_password: false,
+        activation: None,
+    };
+    users.push(admin.clone());
+    save_users(&users).map_err(|e| ApiError::storage("users", e))?;
+    drop(users);
+    *setup_token = None;
+    drop(setup_token);
+
+    println!("Created admin account '{}', setup is done", username);
+    // Nobody is logged in yet, the operator holding the token did this
+    audit::record(&state, "setup", "user.create", format!("user:{}", username), None::<&User>, Some(&admin));
+    Ok(HttpResponse::Created()
+        .insert_header(("Location", format!("/api/users/{}", username)))
+        .json(serde_json::json!({"message": "Admin account created"})))
+}
@@ -0,0 +1,328 @@
+// Read-only share links for schedules. A student creates a link with a random
+// token, optionally expiring, and anyone holding it can view the schedule as
+// JSON, an HTML weekly grid or an iCalendar file without logging in, until the
+// student revokes it.
+use std::fs;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::{format_minutes, parse_meeting_time, schedule_of, AppState, Course, ScheduleStatus, DAY_END, DAY_START};
+
+// Days shown in the HTML grid and their iCalendar names, in week order
+const DAYS: [(&str, &str, Weekday); 7] = [
+    ("M", "MO", Weekday::Mon),
+    ("T", "TU", Weekday::Tue),
+    ("W", "WE", Weekday::Wed),
+    ("Th", "TH", Weekday::Thu),
+    ("F", "FR", Weekday::Fri),
+    ("Sa", "SA", Weekday::Sat),
+    ("Su", "SU", Weekday::Sun),
+];
+
+// A token that grants read-only access to one student's schedule
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+pub struct ShareLink {
+    token: String,
+    student: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// Data for creating a share link
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct NewShareLink {
+    // Reminder of who the link was sent to
+    label: Option<String>,
+    // When the link stops working (RFC 3339); never if omitted
+    expires_at: Option<DateTime<Utc>>,
+}
+
+// A schedule as seen through a share link
+#[derive(Serialize, Debug, ToSchema)]
+pub struct SharedSchedule {
+    student: String,
+    status: ScheduleStatus,
+    courses: Vec<Course>,
+}
+
+// Loads share links from file
+pub fn load_share_links() -> Vec<ShareLink> {
+    fs::read_to_string("share_links.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves share links
+fn save_share_links(links: &Vec<ShareLink>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(links)?;
+    fs::write("share_links.json", data)?;
+    Ok(())
+}
+
+// Makes a random token that can't be guessed
+pub fn new_token() -> String {
+    rand::random::<[u8; 24]>().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Revokes every link to a student's schedule, e.g. when the account is deleted
+pub fn revoke_share_links(state: &AppState, username: &str) -> Result<(), ApiError> {
+    let mut links = state.share_links.lock().unwrap();
+    let count = links.len();
+    links.retain(|l| l.student != username);
+    if links.len() != count {
+        save_share_links(&links).map_err(|e| ApiError::storage("share links", e))?;
+    }
+    Ok(())
+}
+
+// Looks up the student behind a token; unknown, revoked and expired tokens all look the same
+pub fn shared_student(state: &AppState, token: &str) -> Result<String, ApiError> {
+    let links = state.share_links.lock().unwrap();
+    links
+        .iter()
+        .find(|l| l.token == token && l.expires_at.is_none_or(|at| at > Utc::now()))
+        .map(|l| l.student.clone())
+        .ok_or(ApiError::ShareLinkNotFound)
+}
+
+// Looks up the schedule behind a token
+fn shared_schedule(state: &AppState, token: &str) -> Result<SharedSchedule, ApiError> {
+    let student = shared_student(state, token)?;
+    let schedule = schedule_of(state, &student);
+    let courses = state.courses.lock().unwrap();
+    let courses = schedule
+        .courses
+        .iter()
+        .filter_map(|sc| courses.iter().find(|c| c.id == sc.course_id).cloned())
+        .collect();
+    Ok(SharedSchedule { student, status: schedule.status, courses })
+}
+
+// Escapes text for HTML
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Escapes text for an iCalendar property value
+fn escape_ics(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+// Renders a schedule as a weekly grid with a row for every half hour
+fn render_html(shared: &SharedSchedule) -> String {
+    let slots: Vec<(&Course, String, u32, u32)> = shared
+        .courses
+        .iter()
+        .flat_map(|course| parse_meeting_time(&course.meeting_time).into_iter().map(move |s| (course, s.day, s.start, s.end)))
+        .collect();
+    // Weekend columns only appear when something meets then
+    let days: Vec<&str> = DAYS
+        .iter()
+        .map(|(day, _, _)| *day)
+        .filter(|day| !matches!(*day, "Sa" | "Su") || slots.iter().any(|(_, d, _, _)| d == day))
+        .collect();
+
+    let title = format!("Schedule of {}", escape_html(&shared.student));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n\
+         table {{ border-collapse: collapse; font-family: sans-serif; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 2px 6px; min-width: 7em; }}\n\
+         td.class {{ background: #dbe9ff; }}\n</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>Status: {}</p>\n<table>\n<tr><th></th>",
+        shared.status,
+    );
+    for day in &days {
+        html.push_str(&format!("<th>{}</th>", day));
+    }
+    html.push_str("</tr>\n");
+
+    for row in (DAY_START..DAY_END).step_by(30) {
+        html.push_str(&format!("<tr><th>{}</th>", format_minutes(row)));
+        for day in &days {
+            let labels: Vec<String> = slots
+                .iter()
+                .filter(|(_, d, start, end)| d == day && *start < row + 30 && *end > row)
+                .map(|(course, _, _, _)| escape_html(&format!("{} {}", course.dept_code, course.course_number)))
+                .collect();
+            if labels.is_empty() {
+                html.push_str("<td></td>");
+            } else {
+                html.push_str(&format!("<td class=\"class\">{}</td>", labels.join("<br>")));
+            }
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
+
+// Renders a schedule as weekly recurring events, one per course and meeting day.
+// Courses carry no term dates, so each series starts on the next matching day and has no end.
+fn render_ics(shared: &SharedSchedule, token: &str) -> String {
+    let now = Utc::now();
+    let today = now.date_naive();
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//Course Scheduler//Shared Schedule//EN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_ics(&format!("Schedule of {}", shared.student))),
+    ];
+
+    for course in &shared.courses {
+        for slot in parse_meeting_time(&course.meeting_time) {
+            let Some((_, byday, weekday)) = DAYS.iter().find(|(day, _, _)| *day == slot.day) else { continue };
+            let days_ahead = (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+            let date = (today + Duration::days(days_ahead as i64)).format("%Y%m%d");
+            let time = |minutes: u32| format!("{:02}{:02}00", minutes / 60, minutes % 60);
+
+            lines.push("BEGIN:VEVENT".to_string());
+            lines.push(format!("UID:{}-{}-{}@course-scheduler", token, course.id, byday));
+            lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+            lines.push(format!("DTSTART:{}T{}", date, time(slot.start)));
+            lines.push(format!("DTEND:{}T{}", date, time(slot.end)));
+            lines.push(format!("RRULE:FREQ=WEEKLY;BYDAY={}", byday));
+            lines.push(format!("SUMMARY:{}", escape_ics(&format!("{} {}", course.dept_code, course.course_number))));
+            lines.push(format!("LOCATION:{}", escape_ics(&course.location)));
+            lines.push(format!("DESCRIPTION:{}", escape_ics(&format!("{} ({})", course.description, course.instructor))));
+            lines.push("END:VEVENT".to_string());
+        }
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+    let mut ics = lines.join("\r\n");
+    ics.push_str("\r\n");
+    ics
+}
+
+// GET /api/me/shares
+#[utoipa::path(
+    get,
+    path = "/api/me/shares",
+    tag = "schedule",
+    responses(
+        (status = 200, description = "Share links to the logged-in student's schedule, including expired ones", body = Vec<ShareLink>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_share_links(user: AuthUser, state: web::Data<AppState>) -> impl Responder {
+    let links = state.share_links.lock().unwrap();
+    let mine: Vec<&ShareLink> = links.iter().filter(|l| l.student == user.username).collect();
+    HttpResponse::Ok().json(mine)
+}
+
+// POST /api/me/shares
+#[utoipa::path(
+    post,
+    path = "/api/me/shares",
+    tag = "schedule",
+    request_body = NewShareLink,
+    responses(
+        (status = 201, description = "Share link created", body = ShareLink),
+        (status = 400, description = "Invalid input", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn create_share_link(user: AuthUser, body: web::Json<NewShareLink>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let body = body.into_inner();
+    if body.expires_at.is_some_and(|at| at <= Utc::now()) {
+        return Err(ApiError::validation("expires_at", "Expiry must be in the future"));
+    }
+
+    let link = ShareLink {
+        token: new_token(),
+        student: user.username.clone(),
+        label: body.label.map(|l| l.trim().to_string()).filter(|l| !l.is_empty()),
+        created_at: Utc::now(),
+        expires_at: body.expires_at,
+    };
+    let mut links = state.share_links.lock().unwrap();
+    links.push(link.clone());
+    save_share_links(&links).map_err(|e| ApiError::storage("share links", e))?;
+    audit::record(&state, &user.username, "share.create", format!("schedule:{}", user.username), None::<&ShareLink>, Some(&link));
+    Ok(HttpResponse::Created().json(link))
+}
+
+// DELETE /api/me/shares/{token}
+#[utoipa::path(
+    delete,
+    path = "/api/me/shares/{token}",
+    tag = "schedule",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 204, description = "Share link revoked"),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "Share link not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn revoke_share_link(user: AuthUser, token: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut links = state.share_links.lock().unwrap();
+    let index = links
+        .iter()
+        .position(|l| l.token == *token && l.student == user.username)
+        .ok_or(ApiError::ShareLinkNotFound)?;
+    let link = links.remove(index);
+    save_share_links(&links).map_err(|e| ApiError::storage("share links", e))?;
+    audit::record(&state, &user.username, "share.revoke", format!("schedule:{}", user.username), Some(&link), None::<&ShareLink>);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// GET /api/shared/{token}
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}",
+    tag = "shared",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "The shared schedule", body = SharedSchedule),
+        (status = 404, description = "Link is unknown, revoked or expired", body = ApiErrorBody),
+    )
+)]
+pub async fn shared_json(token: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    Ok(HttpResponse::Ok().json(shared_schedule(&state, &token)?))
+}
+
+// GET /api/shared/{token}/schedule.html
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}/schedule.html",
+    tag = "shared",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "The shared schedule as a weekly grid", content_type = "text/html", body = String),
+        (status = 404, description = "Link is unknown, revoked or expired", body = ApiErrorBody),
+    )
+)]
+pub async fn shared_html(token: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let shared = shared_schedule(&state, &token)?;
+    Ok(HttpResponse::Ok().content_type("text/html; charset=utf-8").body(render_html(&shared)))
+}
+
+// GET /api/shared/{token}/schedule.ics
+#[utoipa::path(
+    get,
+    path = "/api/shared/{token}/schedule.ics",
+    tag = "shared",
+    params(("token" = String, Path, description = "Share token")),
+    responses(
+        (status = 200, description = "The shared schedule as weekly recurring events", content_type = "text/calendar", body = String),
+        (status = 404, description = "Link is unknown, revoked or expired", body = ApiErrorBody),
+    )
+)]
+pub async fn shared_ics(token: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let shared = shared_schedule(&state, &token)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .insert_header(("Content-Disposition", "attachment; filename=\"schedule.ics\""))
+        .body(render_ics(&shared, &token)))
+}
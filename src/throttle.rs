@@ -0,0 +1,300 @@
+// Login throttling. Failed logins are counted per account and per client IP.
+// After a few free attempts each further failure doubles the wait before the
+// next try, and enough failures in a row lock the account or address for a
+// while. Lockouts are audited and admins can unlock accounts early. Counts
+// are kept in memory and start over when the server restarts.
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use actix_web::{web, HttpResponse, Responder};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::AuthUser;
+use super::error::{ApiError, ApiErrorBody};
+use super::AppState;
+
+// Wait after the first failure past the free attempts, doubled by each further failure
+const BASE_DELAY_SECONDS: i64 = 1;
+
+// Longest wait between attempts before a lockout
+const MAX_DELAY_SECONDS: i64 = 5 * 60;
+
+// How long a lockout lasts
+const LOCKOUT_MINUTES: i64 = 15;
+
+// Failures are forgotten after this long without another one
+const FORGET_AFTER_MINUTES: i64 = 60;
+
+// Failures allowed without waiting, and the failure that locks
+struct Policy {
+    free_attempts: u32,
+    lockout_after: u32,
+}
+
+// Limits for one account
+const ACCOUNT_POLICY: Policy = Policy { free_attempts: 3, lockout_after: 10 };
+
+// Limits for one client address, across every account it tries
+const ADDRESS_POLICY: Policy = Policy { free_attempts: 10, lockout_after: 50 };
+
+// Recent failed logins for one account or address
+#[derive(Clone, Debug)]
+struct Failures {
+    count: u32,
+    last_failure: DateTime<Utc>,
+    // No login is tried before this time
+    blocked_until: Option<DateTime<Utc>>,
+    locked: bool,
+}
+
+// Failed logins being tracked
+#[derive(Default)]
+pub struct LoginAttempts {
+    accounts: HashMap<String, Failures>,
+    addresses: HashMap<IpAddr, Failures>,
+}
+
+// What a lockout applies to
+#[derive(Serialize, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LockoutKind {
+    Account,
+    Address,
+}
+
+// An account or address that currently has to wait or is locked
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Lockout {
+    kind: LockoutKind,
+    // Username or IP address
+    key: String,
+    failures: u32,
+    locked: bool,
+    blocked_until: DateTime<Utc>,
+}
+
+impl Failures {
+    // Rejects an attempt made while blocked
+    fn check(&self, now: DateTime<Utc>) -> Result<(), ApiError> {
+        match self.blocked_until {
+            Some(until) if until > now => {
+                // Round up so a client waiting exactly this long isn't turned away again
+                let seconds = ((until - now).num_milliseconds() + 999).div_euclid(1000).max(1) as u64;
+                Err(if self.locked { ApiError::AccountLocked(seconds) } else { ApiError::LoginThrottled(seconds) })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Counts a failure and works out how long to block, returning whether this failure caused a lockout
+    fn fail(&mut self, policy: &Policy, now: DateTime<Utc>) -> bool {
+        // A lockout that ran out starts the count over
+        if self.locked && self.blocked_until.is_none_or(|until| until <= now) {
+            *self = Failures { count: 0, last_failure: now, blocked_until: None, locked: false };
+        }
+        self.count += 1;
+        self.last_failure = now;
+        if self.count >= policy.lockout_after {
+            let newly_locked = !self.locked;
+            self.locked = true;
+            self.blocked_until = Some(now + Duration::minutes(LOCKOUT_MINUTES));
+            return newly_locked;
+        }
+        if self.count > policy.free_attempts {
+            let doublings = (self.count - policy.free_attempts - 1).min(20);
+            let delay = (BASE_DELAY_SECONDS << doublings).min(MAX_DELAY_SECONDS);
+            self.blocked_until = Some(now + Duration::seconds(delay));
+        }
+        false
+    }
+
+    // Whether the record still matters
+    fn is_current(&self, now: DateTime<Utc>) -> bool {
+        self.blocked_until.is_some_and(|until| until > now) || now - self.last_failure < Duration::minutes(FORGET_AFTER_MINUTES)
+    }
+}
+
+impl Default for Failures {
+    fn default() -> Self {
+        Failures { count: 0, last_failure: Utc::now(), blocked_until: None, locked: false }
+    }
+}
+
+// Rejects a login while the account or the client's address has to wait
+pub fn check_login_allowed(state: &AppState, username: &str, address: Option<IpAddr>) -> Result<(), ApiError> {
+    let attempts = state.login_attempts.lock().unwrap();
+    let now = Utc::now();
+    if let Some(failures) = address.and_then(|a| attempts.addresses.get(&a)) {
+        failures.check(now)?;
+    }
+    if let Some(failures) = attempts.accounts.get(username) {
+        failures.check(now)?;
+    }
+    Ok(())
+}
+
+// Counts a failed login. Only existing accounts are tracked per account, so
+// guessing usernames doesn't fill the table. Returns the lockout error when this
+// failure locked the account.
+pub fn record_login_failure(state: &AppState, username: &str, account_exists: bool, address: Option<IpAddr>) -> Option<ApiError> {
+    let now = Utc::now();
+    let mut lockouts = Vec::new();
+    {
+        let mut attempts = state.login_attempts.lock().unwrap();
+        attempts.accounts.retain(|_, f| f.is_current(now));
+        attempts.addresses.retain(|_, f| f.is_current(now));
+
+        if let Some(address) = address {
+            let failures = attempts.addresses.entry(address).or_default();
+            if failures.fail(&ADDRESS_POLICY, now) {
+                lockouts.push(("login.address_lockout", format!("ip:{}", address), failures.count));
+            }
+        }
+        if account_exists {
+            let failures = attempts.accounts.entry(username.to_string()).or_default();
+            if failures.fail(&ACCOUNT_POLICY, now) {
+                lockouts.push(("user.lockout", format!("user:{}", username), failures.count));
+            }
+        }
+    }
+
+    let mut locked_account = None;
+    for (action, target, count) in lockouts {
+        let details = serde_json::json!({
+            "failures": count,
+            "locked_until": now + Duration::minutes(LOCKOUT_MINUTES),
+            "address": address.map(|a| a.to_string()),
+        });
+        audit::record(state, "system", action, target, None::<&serde_json::Value>, Some(&details));
+        if action == "user.lockout" {
+            locked_account = Some(ApiError::AccountLocked((LOCKOUT_MINUTES * 60) as u64));
+        }
+    }
+    locked_account
+}
+
+// Forgets an account's failures after it logs in
+pub fn record_login_success(state: &AppState, username: &str) {
+    state.login_attempts.lock().unwrap().accounts.remove(username);
+}
+
+// GET /api/lockouts
+#[utoipa::path(
+    get,
+    path = "/api/lockouts",
+    tag = "users",
+    responses(
+        (status = 200, description = "Accounts and addresses that are locked or have to wait before logging in again", body = Vec<Lockout>),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+    )
+)]
+pub async fn list_lockouts(state: web::Data<AppState>) -> impl Responder {
+    let attempts = state.login_attempts.lock().unwrap();
+    let now = Utc::now();
+    let blocked = |f: &Failures| f.blocked_until.filter(|until| *until > now);
+    let accounts = attempts.accounts.iter().filter_map(|(username, f)| {
+        blocked(f).map(|until| Lockout { kind: LockoutKind::Account, key: username.clone(), failures: f.count, locked: f.locked, blocked_until: until })
+    });
+    let addresses = attempts.addresses.iter().filter_map(|(address, f)| {
+        blocked(f).map(|until| Lockout { kind: LockoutKind::Address, key: address.to_string(), failures: f.count, locked: f.locked, blocked_until: until })
+    });
+    let mut lockouts: Vec<Lockout> = accounts.chain(addresses).collect();
+    lockouts.sort_by_key(|l| l.blocked_until);
+    HttpResponse::Ok().json(lockouts)
+}
+
+// POST /api/users/{username}/unlock
+#[utoipa::path(
+    post,
+    path = "/api/users/{username}/unlock",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    responses(
+        (status = 204, description = "Failed logins cleared, the account can log in again"),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+    )
+)]
+pub async fn unlock_account(admin: AuthUser, username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let username = username.into_inner();
+    if !state.users.lock().unwrap().iter().any(|u| u.username == username) {
+        return Err(ApiError::UserNotFound);
+    }
+
+    let cleared = state.login_attempts.lock().unwrap().accounts.remove(&username);
+    if let Some(failures) = cleared {
+        let before = serde_json::json!({ "failures": failures.count, "locked": failures.locked });
+        audit::record(&state, &admin.username, "user.unlock", format!("user:{}", username), Some(&before), None::<&serde_json::Value>);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Seconds the record blocks for after a failure at `now`
+    fn wait(failures: &Failures, now: DateTime<Utc>) -> Option<i64> {
+        failures.blocked_until.map(|until| (until - now).num_seconds())
+    }
+
+    #[test]
+    fn waits_double_after_the_free_attempts() {
+        let now = Utc::now();
+        let mut failures = Failures::default();
+        let waits: Vec<Option<i64>> = (0..ACCOUNT_POLICY.lockout_after - 1)
+            .map(|_| {
+                assert!(!failures.fail(&ACCOUNT_POLICY, now));
+                wait(&failures, now)
+            })
+            .collect();
+
+        assert_eq!(waits, [None, None, None, Some(1), Some(2), Some(4), Some(8), Some(16), Some(32)]);
+        assert!(matches!(failures.check(now), Err(ApiError::LoginThrottled(32))));
+        assert!(failures.check(now + Duration::seconds(32)).is_ok());
+    }
+
+    #[test]
+    fn waits_are_capped_at_five_minutes() {
+        let now = Utc::now();
+        let mut failures = Failures::default();
+        for _ in 0..ADDRESS_POLICY.lockout_after - 1 {
+            failures.fail(&ADDRESS_POLICY, now);
+        }
+
+        assert!(!failures.locked);
+        assert_eq!(wait(&failures, now), Some(MAX_DELAY_SECONDS));
+    }
+
+    #[test]
+    fn failures_lock_once_at_the_limit() {
+        let now = Utc::now();
+        let mut failures = Failures::default();
+        let lockouts: Vec<u32> = (1..=ACCOUNT_POLICY.lockout_after + 1).filter(|_| failures.fail(&ACCOUNT_POLICY, now)).collect();
+
+        // Failing again while locked extends the lockout but isn't a new one
+        assert_eq!(lockouts, [ACCOUNT_POLICY.lockout_after]);
+        assert!(failures.locked);
+        assert_eq!(wait(&failures, now), Some(LOCKOUT_MINUTES * 60));
+        assert!(matches!(failures.check(now + Duration::milliseconds(500)), Err(ApiError::AccountLocked(900))));
+    }
+
+    #[test]
+    fn an_expired_lockout_starts_the_count_over() {
+        let now = Utc::now();
+        let mut failures = Failures::default();
+        for _ in 0..ACCOUNT_POLICY.lockout_after {
+            failures.fail(&ACCOUNT_POLICY, now);
+        }
+
+        let later = now + Duration::minutes(LOCKOUT_MINUTES);
+        assert!(failures.check(later).is_ok());
+        assert!(!failures.fail(&ACCOUNT_POLICY, later));
+        assert_eq!(failures.count, 1);
+        assert!(!failures.locked);
+        assert_eq!(wait(&failures, later), None);
+    }
+}
@@ -0,0 +1,542 @@
+// Two-factor authentication with time-based one-time passwords (RFC 6238).
+// Users enroll an authenticator app and get single-use recovery codes for
+// when the app isn't at hand. Once enrolled, `login` only checks the password
+// and hands out a short-lived challenge, and the session starts after a code
+// is sent to POST /api/login/two-factor. Admin accounts must enroll before
+// they can use anything else; for everyone else it is optional.
+use std::collections::HashMap;
+use std::fs;
+
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+use utoipa::ToSchema;
+
+use super::audit;
+use super::auth::{AuthUser, Role};
+use super::error::{ApiError, ApiErrorBody};
+use super::passwords::hash_token;
+use super::share::new_token;
+use super::throttle::{check_login_allowed, record_login_failure, record_login_success};
+use super::{save_session, AppState, LoginResponse, Session};
+
+// Name authenticator apps show next to the account
+const ISSUER: &str = "Course Scheduler";
+
+// Digits in a code and seconds each code is valid for, the defaults every authenticator app expects
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+
+// Secret size in bytes, the 160 bits RFC 4226 recommends
+const SECRET_BYTES: usize = 20;
+
+// How long the second login step can wait after the password was accepted
+const CHALLENGE_MINUTES: i64 = 5;
+
+// Recovery codes handed out at a time, and characters in each
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+// Characters recovery codes are made of, without ones that are easy to misread
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+// An account's authenticator enrollment
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TwoFactor {
+    // Base32 shared secret, as entered into the authenticator app
+    secret: String,
+    // False until the first code confirms the app is set up
+    enabled: bool,
+    // SHA-256 of each unused recovery code
+    #[serde(default)]
+    recovery_codes: Vec<String>,
+    // Last time step a code was accepted for, so a code can't be used twice
+    #[serde(default)]
+    last_step: u64,
+}
+
+// A login whose password was accepted and which still needs a code
+pub struct PendingLogin {
+    challenge: String,
+    username: String,
+    expires_at: DateTime<Utc>,
+}
+
+// Whether two-factor authentication is on for the logged-in user
+#[derive(Serialize, Debug, ToSchema)]
+pub struct TwoFactorStatus {
+    enabled: bool,
+    // Admin accounts can't turn it off
+    required: bool,
+    recovery_codes_left: usize,
+}
+
+// Confirms the logged-in user's password before enrollment starts
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct TwoFactorStart {
+    password: String,
+}
+
+// Secret to add to an authenticator app, by hand or as a QR code of the URL
+#[derive(Serialize, Debug, ToSchema)]
+pub struct TwoFactorSetup {
+    secret: String,
+    otpauth_url: String,
+}
+
+// A code from the authenticator app
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct TwoFactorCode {
+    code: String,
+}
+
+// Turns two-factor authentication off
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct TwoFactorDisable {
+    password: String,
+    code: String,
+}
+
+// Recovery codes, shown only once
+#[derive(Serialize, Debug, ToSchema)]
+pub struct RecoveryCodes {
+    recovery_codes: Vec<String>,
+}
+
+// Second login step
+#[derive(Deserialize, Debug, ToSchema)]
+pub struct TwoFactorLogin {
+    // Challenge from the `login` response
+    challenge: String,
+    // Code from the authenticator app, or one of the recovery codes
+    code: String,
+}
+
+impl TwoFactor {
+    // Authenticator for the secret, labelled with the account
+    fn totp(&self, username: &str) -> Option<TOTP> {
+        let secret = Secret::Encoded(self.secret.clone()).to_bytes().ok()?;
+        Some(TOTP::new_unchecked(Algorithm::SHA1, DIGITS, 0, STEP_SECONDS, secret, Some(ISSUER.to_string()), username.to_string()))
+    }
+
+    // Accepts a code from the app once. Codes from the step before and after are
+    // allowed too, for clocks that are slightly off.
+    fn accept_code(&mut self, username: &str, code: &str, now: DateTime<Utc>) -> bool {
+        let Some(totp) = self.totp(username) else {
+            return false;
+        };
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        let current = now.timestamp().max(0) as u64 / STEP_SECONDS;
+        for step in current.saturating_sub(1)..=current + 1 {
+            if step > self.last_step && totp.check(&code, step * STEP_SECONDS) {
+                self.last_step = step;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Uses up a recovery code
+    fn accept_recovery_code(&mut self, code: &str) -> bool {
+        let hash = hash_token(&normalize_recovery_code(code));
+        let count = self.recovery_codes.len();
+        self.recovery_codes.retain(|h| *h != hash);
+        self.recovery_codes.len() != count
+    }
+}
+
+// Loads enrollments by username
+pub fn load_two_factor() -> HashMap<String, TwoFactor> {
+    fs::read_to_string("two_factor.json")
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+// Saves enrollments
+fn save_two_factor(enrollments: &HashMap<String, TwoFactor>) -> Result<(), std::io::Error> {
+    let data = serde_json::to_string_pretty(enrollments)?;
+    fs::write("two_factor.json", data)?;
+    Ok(())
+}
+
+// Recovery codes are compared without case, spaces or dashes
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_alphanumeric()).collect::<String>().to_lowercase()
+}
+
+// Makes a fresh set of recovery codes, returning them for display and hashed for storage
+fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code: String = (0..RECOVERY_CODE_LENGTH)
+                .map(|_| RECOVERY_ALPHABET[rand::random_range(0..RECOVERY_ALPHABET.len())] as char)
+                .collect();
+            format!("{}-{}", &code[..RECOVERY_CODE_LENGTH / 2], &code[RECOVERY_CODE_LENGTH / 2..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|c| hash_token(&normalize_recovery_code(c))).collect();
+    (codes, hashes)
+}
+
+// Whether the user has finished enrolling
+pub fn two_factor_enabled(state: &AppState, username: &str) -> bool {
+    state.two_factor.lock().unwrap().get(username).is_some_and(|t| t.enabled)
+}
+
+// Starts the second login step, returning the challenge to answer with a code
+pub fn start_two_factor_login(state: &AppState, username: &str) -> String {
+    let challenge = new_token();
+    let mut pending = state.pending_logins.lock().unwrap();
+    pending.retain(|p| p.username != username && p.expires_at > Utc::now());
+    pending.push(PendingLogin {
+        challenge: challenge.clone(),
+        username: username.to_string(),
+        expires_at: Utc::now() + Duration::minutes(CHALLENGE_MINUTES),
+    });
+    challenge
+}
+
+// Drops a user's enrollment and pending logins, e.g. when the account is deleted
+pub fn remove_two_factor(state: &AppState, username: &str) -> Result<bool, ApiError> {
+    state.pending_logins.lock().unwrap().retain(|p| p.username != username);
+    let mut enrollments = state.two_factor.lock().unwrap();
+    if enrollments.remove(username).is_none() {
+        return Ok(false);
+    }
+    save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+    Ok(true)
+}
+
+// Checks the logged-in user's password again before a security change
+fn confirm_password(state: &AppState, username: &str, password: &str) -> Result<(), ApiError> {
+    let users = state.users.lock().unwrap();
+    match users.iter().find(|u| u.username == username) {
+        Some(user) if user.password == password => Ok(()),
+        _ => Err(ApiError::InvalidCredentials),
+    }
+}
+
+// GET /api/me/two-factor
+#[utoipa::path(
+    get,
+    path = "/api/me/two-factor",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Two-factor status of the logged-in user", body = TwoFactorStatus),
+        (status = 401, description = "Not logged in", body = ApiErrorBody),
+    )
+)]
+pub async fn two_factor_status(user: AuthUser, state: web::Data<AppState>) -> HttpResponse {
+    let enrollments = state.two_factor.lock().unwrap();
+    let enrollment = enrollments.get(&user.username).filter(|t| t.enabled);
+    HttpResponse::Ok().json(TwoFactorStatus {
+        enabled: enrollment.is_some(),
+        required: user.role == Role::Admin,
+        recovery_codes_left: enrollment.map_or(0, |t| t.recovery_codes.len()),
+    })
+}
+
+// POST /api/me/two-factor
+#[utoipa::path(
+    post,
+    path = "/api/me/two-factor",
+    tag = "auth",
+    request_body = TwoFactorStart,
+    responses(
+        (status = 200, description = "New secret for the authenticator app; confirm it with a code to finish", body = TwoFactorSetup),
+        (status = 400, description = "Two-factor authentication is already on", body = ApiErrorBody),
+        (status = 401, description = "Not logged in, or the password is wrong", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn start_enrollment(user: AuthUser, body: web::Json<TwoFactorStart>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    confirm_password(&state, &user.username, &body.password)?;
+
+    let mut enrollments = state.two_factor.lock().unwrap();
+    if enrollments.get(&user.username).is_some_and(|t| t.enabled) {
+        return Err(ApiError::InvalidRequest("Two-factor authentication is already on".to_string()));
+    }
+    // Starting over replaces a secret that was never confirmed
+    let secret = Secret::Raw(rand::random::<[u8; SECRET_BYTES]>().to_vec()).to_encoded().to_string();
+    let enrollment = TwoFactor { secret: secret.clone(), enabled: false, recovery_codes: Vec::new(), last_step: 0 };
+    let otpauth_url = enrollment
+        .totp(&user.username)
+        .map(|t| t.get_url())
+        .ok_or_else(|| ApiError::InvalidRequest("Failed to create an authenticator secret".to_string()))?;
+    enrollments.insert(user.username.clone(), enrollment);
+    save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+    Ok(HttpResponse::Ok().json(TwoFactorSetup { secret, otpauth_url }))
+}
+
+// POST /api/me/two-factor/confirm
+#[utoipa::path(
+    post,
+    path = "/api/me/two-factor/confirm",
+    tag = "auth",
+    request_body = TwoFactorCode,
+    responses(
+        (status = 200, description = "Two-factor authentication is on; store the recovery codes somewhere safe", body = RecoveryCodes),
+        (status = 400, description = "Enrollment wasn't started, or is already finished", body = ApiErrorBody),
+        (status = 401, description = "Not logged in, or the code is wrong", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn confirm_enrollment(user: AuthUser, body: web::Json<TwoFactorCode>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut enrollments = state.two_factor.lock().unwrap();
+    let enrollment = enrollments
+        .get_mut(&user.username)
+        .filter(|t| !t.enabled)
+        .ok_or_else(|| ApiError::InvalidRequest("Start two-factor setup with POST /api/me/two-factor first".to_string()))?;
+    if !enrollment.accept_code(&user.username, &body.code, Utc::now()) {
+        return Err(ApiError::InvalidTwoFactorCode);
+    }
+    let (codes, hashes) = generate_recovery_codes();
+    enrollment.enabled = true;
+    enrollment.recovery_codes = hashes;
+    save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+    drop(enrollments);
+
+    audit::record(&state, &user.username, "user.two_factor_enable", format!("user:{}", user.username), None::<&()>, None::<&()>);
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes: codes }))
+}
+
+// POST /api/me/two-factor/recovery-codes
+#[utoipa::path(
+    post,
+    path = "/api/me/two-factor/recovery-codes",
+    tag = "auth",
+    request_body = TwoFactorCode,
+    responses(
+        (status = 200, description = "New recovery codes, the old ones no longer work", body = RecoveryCodes),
+        (status = 400, description = "Two-factor authentication is off", body = ApiErrorBody),
+        (status = 401, description = "Not logged in, or the code is wrong", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn regenerate_recovery_codes(user: AuthUser, body: web::Json<TwoFactorCode>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let mut enrollments = state.two_factor.lock().unwrap();
+    let enrollment = enrollments
+        .get_mut(&user.username)
+        .filter(|t| t.enabled)
+        .ok_or_else(|| ApiError::InvalidRequest("Two-factor authentication is off".to_string()))?;
+    if !enrollment.accept_code(&user.username, &body.code, Utc::now()) {
+        return Err(ApiError::InvalidTwoFactorCode);
+    }
+    let (codes, hashes) = generate_recovery_codes();
+    enrollment.recovery_codes = hashes;
+    save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+    drop(enrollments);
+
+    audit::record(&state, &user.username, "user.recovery_codes", format!("user:{}", user.username), None::<&()>, None::<&()>);
+    Ok(HttpResponse::Ok().json(RecoveryCodes { recovery_codes: codes }))
+}
+
+// DELETE /api/me/two-factor
+#[utoipa::path(
+    delete,
+    path = "/api/me/two-factor",
+    tag = "auth",
+    request_body = TwoFactorDisable,
+    responses(
+        (status = 204, description = "Two-factor authentication turned off"),
+        (status = 400, description = "Two-factor authentication is already off", body = ApiErrorBody),
+        (status = 401, description = "Not logged in, or the password or code is wrong", body = ApiErrorBody),
+        (status = 403, description = "Admin accounts have to keep it on", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn disable_two_factor(user: AuthUser, body: web::Json<TwoFactorDisable>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    if user.role == Role::Admin {
+        return Err(ApiError::Forbidden("Two-factor authentication is required for admin accounts".to_string()));
+    }
+    confirm_password(&state, &user.username, &body.password)?;
+
+    let mut enrollments = state.two_factor.lock().unwrap();
+    let enrollment = enrollments
+        .get_mut(&user.username)
+        .filter(|t| t.enabled)
+        .ok_or_else(|| ApiError::InvalidRequest("Two-factor authentication is off".to_string()))?;
+    if !enrollment.accept_code(&user.username, &body.code, Utc::now()) && !enrollment.accept_recovery_code(&body.code) {
+        return Err(ApiError::InvalidTwoFactorCode);
+    }
+    enrollments.remove(&user.username);
+    save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+    drop(enrollments);
+
+    audit::record(&state, &user.username, "user.two_factor_disable", format!("user:{}", user.username), None::<&()>, None::<&()>);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+// POST /api/login/two-factor
+#[utoipa::path(
+    post,
+    path = "/api/login/two-factor",
+    tag = "auth",
+    request_body = TwoFactorLogin,
+    responses(
+        (status = 200, description = "Logged in", body = LoginResponse),
+        (status = 400, description = "The challenge is unknown or expired; log in again", body = ApiErrorBody),
+        (status = 401, description = "The code is wrong or was already used", body = ApiErrorBody),
+        (status = 403, description = "Another user is already logged in", body = ApiErrorBody),
+        (status = 429, description = "Too many failed logins for the account or address, see Retry-After", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn login_two_factor(req: HttpRequest, body: web::Json<TwoFactorLogin>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let address = req.peer_addr().map(|a| a.ip());
+    let username = state
+        .pending_logins
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|p| p.challenge == body.challenge && p.expires_at > Utc::now())
+        .map(|p| p.username.clone())
+        .ok_or(ApiError::InvalidToken)?;
+    // Wrong codes count as failed logins, so they are throttled like passwords
+    check_login_allowed(&state, &username, address)?;
+
+    let recovery_codes_left = {
+        let mut enrollments = state.two_factor.lock().unwrap();
+        let enrollment = enrollments.get_mut(&username).filter(|t| t.enabled).ok_or(ApiError::InvalidToken)?;
+        let recovery_codes_left = if enrollment.accept_code(&username, &body.code, Utc::now()) {
+            None
+        } else if enrollment.accept_recovery_code(&body.code) {
+            Some(enrollment.recovery_codes.len())
+        } else {
+            drop(enrollments);
+            let locked = record_login_failure(&state, &username, true, address);
+            return Err(locked.unwrap_or(ApiError::InvalidTwoFactorCode));
+        };
+        save_two_factor(&enrollments).map_err(|e| ApiError::storage("two-factor settings", e))?;
+        recovery_codes_left
+    };
+
+    let users = state.users.lock().unwrap();
+    let mut session = state.session.lock().unwrap();
+    if session.is_some() {
+        return Err(ApiError::SessionActive);
+    }
+    let user = users.iter().find(|u| u.username == username).ok_or(ApiError::InvalidToken)?;
+    state.pending_logins.lock().unwrap().retain(|p| p.username != username);
+    record_login_success(&state, &username);
+
+    *session = Some(Session { username: username.clone() });
+    save_session(&session).map_err(|e| ApiError::storage("session", e))?;
+    let response = LoginResponse {
+        role: user.role,
+        must_change_password: user.must_change_password,
+        two_factor_challenge: None,
+        two_factor_setup_required: false,
+    };
+    drop(session);
+    drop(users);
+
+    if let Some(left) = recovery_codes_left {
+        let details = serde_json::json!({ "recovery_codes_left": left });
+        audit::record(&state, &username, "user.recovery_code_used", format!("user:{}", username), None::<&serde_json::Value>, Some(&details));
+    }
+    Ok(HttpResponse::Ok().json(response))
+}
+
+// DELETE /api/users/{username}/two-factor
+#[utoipa::path(
+    delete,
+    path = "/api/users/{username}/two-factor",
+    tag = "users",
+    params(("username" = String, Path, description = "Account username")),
+    responses(
+        (status = 204, description = "Two-factor authentication removed, e.g. after the user lost their device and recovery codes"),
+        (status = 400, description = "Admin accounts can't be changed", body = ApiErrorBody),
+        (status = 403, description = "Not allowed for the current session", body = ApiErrorBody),
+        (status = 404, description = "User not found", body = ApiErrorBody),
+        (status = 500, description = "Failed to save data", body = ApiErrorBody),
+    )
+)]
+pub async fn reset_two_factor(admin: AuthUser, username: web::Path<String>, state: web::Data<AppState>) -> Result<HttpResponse, ApiError> {
+    let username = username.into_inner();
+    let role = state.users.lock().unwrap().iter().find(|u| u.username == username).map(|u| u.role);
+    match role {
+        None => return Err(ApiError::UserNotFound),
+        Some(Role::Admin) => return Err(ApiError::ProtectedAccount("Cannot modify admin account".to_string())),
+        Some(_) => {}
+    }
+
+    if remove_two_factor(&state, &username)? {
+        audit::record(&state, &admin.username, "user.two_factor_reset", format!("user:{}", username), None::<&()>, None::<&()>);
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+
+    // A time step well past zero, so the steps around it are all valid
+    const STEP: u64 = 58_000_000;
+
+    fn enrollment() -> TwoFactor {
+        TwoFactor { secret: SECRET.to_string(), enabled: true, recovery_codes: Vec::new(), last_step: 0 }
+    }
+
+    // The app's code for a time step
+    fn code_at(step: u64) -> String {
+        enrollment().totp("student").unwrap().generate(step * STEP_SECONDS)
+    }
+
+    // A moment inside the time step
+    fn during(step: u64) -> DateTime<Utc> {
+        Utc.timestamp_opt((step * STEP_SECONDS + 10) as i64, 0).unwrap()
+    }
+
+    #[test]
+    fn a_code_is_accepted_once() {
+        let mut two_factor = enrollment();
+        let code = code_at(STEP);
+
+        assert!(two_factor.accept_code("student", &code, during(STEP)));
+        assert_eq!(two_factor.last_step, STEP);
+        assert!(!two_factor.accept_code("student", &code, during(STEP)));
+        assert!(!two_factor.accept_code("student", "000000", during(STEP)));
+    }
+
+    #[test]
+    fn codes_one_step_off_are_accepted() {
+        let code = code_at(STEP - 1);
+        assert!(enrollment().accept_code("student", &code, during(STEP)));
+        let code = code_at(STEP + 1);
+        assert!(enrollment().accept_code("student", &format!("{} {}", &code[..3], &code[3..]), during(STEP)));
+
+        assert!(!enrollment().accept_code("student", &code_at(STEP + 2), during(STEP)));
+        assert!(!enrollment().accept_code("student", &code_at(STEP - 2), during(STEP)));
+    }
+
+    #[test]
+    fn codes_up_to_the_last_accepted_step_are_rejected() {
+        let mut two_factor = enrollment();
+        assert!(two_factor.accept_code("student", &code_at(STEP + 1), during(STEP)));
+
+        // Still within the window, but the app already moved past these steps
+        assert!(!two_factor.accept_code("student", &code_at(STEP), during(STEP)));
+        assert!(!two_factor.accept_code("student", &code_at(STEP + 1), during(STEP + 1)));
+        assert!(two_factor.accept_code("student", &code_at(STEP + 2), during(STEP + 1)));
+    }
+
+    #[test]
+    fn recovery_codes_work_once() {
+        let (codes, hashes) = generate_recovery_codes();
+        let mut two_factor = TwoFactor { recovery_codes: hashes, ..enrollment() };
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(two_factor.accept_recovery_code(&format!(" {} ", codes[0].to_uppercase())));
+        assert!(!two_factor.accept_recovery_code(&codes[0]));
+        assert!(two_factor.accept_recovery_code(&codes[1].replace('-', "")));
+        assert_eq!(two_factor.recovery_codes.len(), RECOVERY_CODE_COUNT - 2);
+        assert!(!two_factor.accept_recovery_code("not-a-code"));
+    }
+}
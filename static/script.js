@@ -1,3 +1,67 @@
+//Asks for a new password to replace a generated one, returns whether it was changed
+async function changeInitialPassword(currentPassword){
+    const newPassword=prompt('Your password was generated for you. Choose a new password:');
+    if(!newPassword)
+        return false;
+    const response=await fetch('/api/me/password',{
+        method:'POST',
+        headers:{'Content-Type':'application/json'},
+        body:JSON.stringify({current_password:currentPassword,new_password:newPassword})
+    });
+    if(!response.ok){
+        const data=await response.json();
+        alert(`Couldn’t change password:${data.message}`);
+    }
+    return response.ok;
+}
+
+//Asks for a code from the authenticator app to finish logging in, returns the login response or null
+async function finishTwoFactorLogin(challenge){
+    const code=prompt('Enter the code from your authenticator app, or a recovery code:');
+    if(!code)
+        return null;
+    const response=await fetch('/api/login/two-factor',{
+        method:'POST',
+        headers:{'Content-Type':'application/json'},
+        body:JSON.stringify({challenge,code})
+    });
+    const data=await response.json();
+    if(!response.ok){
+        alert(`Couldn’t log in:${data.message}`);
+        return null;
+    }
+    return data;
+}
+
+//Walks an admin through adding an authenticator app, returns whether it was set up
+async function setUpTwoFactor(password){
+    const start=await fetch('/api/me/two-factor',{
+        method:'POST',
+        headers:{'Content-Type':'application/json'},
+        body:JSON.stringify({password})
+    });
+    const setup=await start.json();
+    if(!start.ok){
+        alert(`Couldn’t start two-factor setup:${setup.message}`);
+        return false;
+    }
+    const code=prompt(`Admin accounts need two-factor authentication. Add this key to your authenticator app:\n\n${setup.secret}\n\nThen enter the code it shows:`);
+    if(!code)
+        return false;
+    const confirm=await fetch('/api/me/two-factor/confirm',{
+        method:'POST',
+        headers:{'Content-Type':'application/json'},
+        body:JSON.stringify({code})
+    });
+    const data=await confirm.json();
+    if(!confirm.ok){
+        alert(`Couldn’t turn on two-factor authentication:${data.message}`);
+        return false;
+    }
+    alert(`Two-factor authentication is on. Keep these recovery codes somewhere safe, each works once:\n\n${data.recovery_codes.join('\n')}`);
+    return true;
+}
+
 //Adding a listener for the login form submission
 document.getElementById('login-form')?.addEventListener('submit',async(e) => {
     e.preventDefault();
@@ -13,10 +77,32 @@ document.getElementById('login-form')?.addEventListener('submit',async(e) => {
             body:JSON.stringify(credentials)
         });
         const message=document.getElementById('message');
-        const data=await response.json();
+        let data=await response.json();
+        //Accounts with two-factor authentication need a code before the session starts
+        if(response.ok&&data.two_factor_challenge){
+            data=await finishTwoFactorLogin(data.two_factor_challenge);
+            if(!data){
+                message.textContent='Two-factor code needed to log in.';
+                message.style.color='#ff4757';
+                return;
+            }
+        }
         if(response.ok){
             //Worked fine, so log it and redirect based on role
             console.log('Logged in, role is:',data.role);
+            //Accounts with a generated password have to pick their own first
+            if(data.must_change_password&&!await changeInitialPassword(credentials.password)){
+                message.textContent='Choose a new password to continue.';
+                message.style.color='#ff4757';
+                await fetch('/api/logout',{method:'POST'});
+                return;
+            }
+            if(data.two_factor_setup_required&&!await setUpTwoFactor(credentials.password)){
+                message.textContent='Set up two-factor authentication to continue.';
+                message.style.color='#ff4757';
+                await fetch('/api/logout',{method:'POST'});
+                return;
+            }
             if(data.role==='admin')
                 window.location.href='admin.html';
             else if(data.role==='student'){
@@ -24,7 +110,7 @@ document.getElementById('login-form')?.addEventListener('submit',async(e) => {
             }
         }else{
             //Show the error if something’s off
-            message.textContent=data.error;
+            message.textContent=data.message;
             message.style.color='#ff4757';
         }
     }catch(err){
@@ -34,6 +120,43 @@ document.getElementById('login-form')?.addEventListener('submit',async(e) => {
     }
 });
 
+//Swaps the login form for the setup form while there is no admin account yet
+async function checkSetup(){
+    const setupForm=document.getElementById('setup-form');
+    if(!setupForm)
+        return;
+    const response=await fetch('/api/setup');
+    const data=await response.json();
+    setupForm.hidden=!data.required;
+    document.getElementById('login-form').hidden=data.required;
+}
+
+//Creates the first admin account from the setup form
+document.getElementById('setup-form')?.addEventListener('submit',async(e) => {
+    e.preventDefault();
+    const message=document.getElementById('message');
+    const response=await fetch('/api/setup',{
+        method:'POST',
+        headers:{'Content-Type':'application/json'},
+        body:JSON.stringify({
+            token:document.getElementById('setup-token').value,
+            username:document.getElementById('setup-username').value,
+            password:document.getElementById('setup-password').value
+        })
+    });
+    if(response.ok){
+        message.textContent='Admin account created, you can log in now.';
+        message.style.color='';
+        await checkSetup();
+    }else{
+        const data=await response.json();
+        message.textContent=data.message;
+        message.style.color='#ff4757';
+    }
+});
+
+checkSetup();
+
 //Handles logging out when called
 async function logout(){
     try{
@@ -47,7 +170,7 @@ async function logout(){
             window.location.href='index.html';
         }else{
             const data=await response.json();
-            alert(`Logout failed:${data.error}`);
+            alert(`Logout failed:${data.message}`);
         }
     }catch(err){
         //Something broke, let the user know
@@ -68,7 +191,7 @@ document.getElementById('add-course-form')?.addEventListener('submit',async(e) =
         meeting_time:getMeetingTimeString('day','start-hour','start-minute','end-hour','end-minute')
     };
     try{
-        const response=await fetch('/api/admin/add_course',{
+        const response=await fetch('/api/courses',{
             method:'POST',
             headers:{'Content-Type':'application/json'},
             body:JSON.stringify(course)
@@ -82,7 +205,7 @@ document.getElementById('add-course-form')?.addEventListener('submit',async(e) =
             document.getElementById('add-course-form').reset();
             loadCoursesAdmin();
         }else{
-            message.textContent=`Couldn’t add course:${data.error}`;
+            message.textContent=`Couldn’t add course:${data.message}`;
             message.style.color='#ff4757';
         }
     }catch(err){
@@ -92,6 +215,44 @@ document.getElementById('add-course-form')?.addEventListener('submit',async(e) =
     }
 });
 
+//Admin form for importing a CSV or XLSX file of courses, checking it first if asked
+document.getElementById('import-course-form')?.addEventListener('submit',async(e) => {
+    e.preventDefault();
+    const file=document.getElementById('import-file').files[0];
+    const dryRun=e.submitter?.value==='check';
+    const message=document.getElementById('import-message');
+    const errorList=document.getElementById('import-errors');
+    errorList.innerHTML='';
+    try{
+        const response=await fetch(`/api/courses/import?dry_run=${dryRun}`,{
+            method:'POST',
+            body:file
+        });
+        const data=await response.json();
+        //Failed imports list their rows in details, dry runs in errors
+        const rowErrors=response.ok?data.errors:(data.details||[]);
+        rowErrors.forEach(err => {
+            const item=document.createElement('li');
+            item.textContent=`Row ${err.row}${err.column?` (${err.column})`:''}: ${err.message}`;
+            errorList.appendChild(item);
+        });
+        if(response.ok&&rowErrors.length===0){
+            message.textContent=dryRun?`All ${data.rows} rows look good.`:`Imported ${data.imported.length} courses!`;
+            message.style.color='#00ff87';
+            if(!dryRun){
+                document.getElementById('import-course-form').reset();
+                loadCoursesAdmin();
+            }
+        }else{
+            message.textContent=response.ok?`${rowErrors.length} rows have problems:`:`Import failed:${data.message}`;
+            message.style.color='#ff4757';
+        }
+    }catch(err){
+        console.error("Course import error:",err);
+        message.textContent='Import failed.Try again.';
+    }
+});
+
 //Admin form for adding a new student user
 document.getElementById('add-user-form')?.addEventListener('submit',async(e) => {
     e.preventDefault();
@@ -101,7 +262,7 @@ document.getElementById('add-user-form')?.addEventListener('submit',async(e) =>
         role:"student" //Always a student here
     };
     try{
-        const response=await fetch('/api/admin/add_user',{
+        const response=await fetch('/api/users',{
             method: 'POST',
             headers:{'Content-Type':'application/json'},
             body:JSON.stringify(user)
@@ -115,7 +276,7 @@ document.getElementById('add-user-form')?.addEventListener('submit',async(e) =>
             loadUsersAdmin(); //Refresh the list
         }else{
             //Display the error if it fails
-            message.textContent=`Failed to add:${data.error}`;
+            message.textContent=`Failed to add:${data.message}`;
             message.style.color='#ff4757';
         }
     }catch(err){
@@ -168,7 +329,7 @@ function getAdminFilters(){
 //Loads the student list for admin view
 async function loadUsersAdmin(){
     try{
-        const response=await fetch('/api/admin/get_users');
+        const response=await fetch('/api/users');
         if(!response.ok)throw new Error('Users fetch failed');
         const users=await response.json();
         const userList=document.getElementById('user-list');
@@ -195,16 +356,14 @@ async function loadUsersAdmin(){
 async function deleteUser(username){
     if(!confirm(`Really delete '${username}'?`))return;
     try{
-        const response=await fetch('/api/admin/delete_user',{
-            method:'POST',
-            headers:{'Content-Type': 'application/json'},
-            body:JSON.stringify(username)
+        const response=await fetch(`/api/users/${encodeURIComponent(username)}`,{
+            method:'DELETE'
         });
-        const data=await response.json();
         if(response.ok){
             loadUsersAdmin(); //Update the list
         }else{
-            alert(`Delete failed:${data.error}`);
+            const data=await response.json();
+            alert(`Delete failed:${data.message}`);
         }
     }catch(err){
         console.error("Delete user error:",err);
@@ -273,7 +432,7 @@ async function loadSchedule(){
     calendar.innerHTML="<p>Loading...</p>";
 
     try{
-        const response=await fetch('/api/student/schedule');
+        const response=await fetch('/api/me/schedule');
         console.log("Fetch status:",response.status);
         if(!response.ok){
             console.error("Fetch failed:",response.status);
@@ -459,7 +618,6 @@ async function editCourse(courseId){
             }
 
             const newData={
-                id:courseId,
                 dept_code:document.getElementById('edit-dept').value,
                 course_number:document.getElementById('edit-number').value,
                 instructor:document.getElementById('edit-instructor').value,
@@ -468,8 +626,8 @@ async function editCourse(courseId){
                 meeting_time:meetingTime
             };
             try{
-                const response=await fetch('/api/admin/update_course',{
-                    method:'POST',
+                const response=await fetch(`/api/courses/${courseId}`,{
+                    method:'PUT',
                     headers:{'Content-Type':'application/json'},
                     body:JSON.stringify(newData)
                 });
@@ -478,7 +636,7 @@ async function editCourse(courseId){
                     popup.style.display='none';
                     loadCoursesAdmin();
                 }else{
-                    alert(`Update failed:${data.error}`);
+                    alert(`Update failed:${data.message}`);
                 }
             }catch(err){
                 console.error("Update error:",err);
@@ -498,17 +656,15 @@ async function editCourse(courseId){
 async function deleteCourse(courseId){
     if(!confirm("Sure you want to delete this course?"))return;
     try{
-        const response=await fetch('/api/admin/delete_course',{
-            method:'POST',
-            headers:{'Content-Type': 'application/json'},
-            body:JSON.stringify(courseId)
+        const response=await fetch(`/api/courses/${courseId}`,{
+            method:'DELETE'
         });
-        const data=await response.json();
         if(response.ok){
             document.getElementById('edit-popup').style.display='none';
             loadCoursesAdmin();
         }else{
-            alert(`Delete failed:${data.error}`);
+            const data=await response.json();
+            alert(`Delete failed:${data.message}`);
         }
     }catch(err){
         console.error("Delete course error:",err);
@@ -519,16 +675,14 @@ async function deleteCourse(courseId){
 //Adds a course to the student’s schedule
 async function addToSchedule(courseId){
     try{
-        const response=await fetch('/api/student/add_to_schedule',{
-            method:'POST',
-            headers:{'Content-Type':'application/json'},
-            body:JSON.stringify(courseId)
+        const response=await fetch(`/api/me/schedule/${courseId}`,{
+            method:'PUT'
         });
         const data=await response.json();
         if(response.ok){
             loadSchedule(); //Refresh the calendar
         }else{
-            alert(`Couldn’t add to schedule:${data.error}`);
+            alert(`Couldn’t add to schedule:${data.message}`);
         }
     }catch(err){
         console.error('Add to schedule failed:',err);
@@ -539,7 +693,7 @@ async function addToSchedule(courseId){
 //Edits notes or slot for a scheduled course
 async function editScheduleEntry(courseId){
     try{
-        const response=await fetch('/api/student/schedule');
+        const response=await fetch('/api/me/schedule');
         if(!response.ok)throw new Error('Schedule fetch failed');
         const schedule=await response.json();
         const entry=schedule.find(([sc])=>sc.course_id===courseId);
@@ -555,12 +709,11 @@ async function editScheduleEntry(courseId){
         document.getElementById('notes-form').onsubmit=async(e) => {
             e.preventDefault();
             const updatedEntry={
-                course_id:courseId,
                 notes:document.getElementById('notes-text').value,
                 slot:document.getElementById('notes-slot').value
             };
-            const response=await fetch('/api/student/update_schedule_entry',{
-                method:'POST',
+            const response=await fetch(`/api/me/schedule/${courseId}`,{
+                method:'PATCH',
                 headers:{'Content-Type':'application/json'},
                 body:JSON.stringify(updatedEntry)
             });
@@ -569,7 +722,7 @@ async function editScheduleEntry(courseId){
                 loadSchedule();
             }else{
                 const data=await response.json();
-                alert(`Couldn’t save notes:${data.error}`);
+                alert(`Couldn’t save notes:${data.message}`);
             }
         };
     }catch(err){
@@ -582,20 +735,18 @@ async function dropFromSchedule(courseId){
     if(!confirm("Really drop this course?"))return;
     console.log('Trying to drop course ID:',courseId);
     try{
-        const response=await fetch('/api/student/drop_from_schedule',{
-            method:'POST',
-            headers:{'Content-Type': 'application/json'},
-            body:JSON.stringify(courseId)
+        const response=await fetch(`/api/me/schedule/${courseId}`,{
+            method:'DELETE'
         });
-        const data=await response.json();
-        console.log('Drop response:',response.status,data);
+        console.log('Drop response:',response.status);
         if(response.ok){
             document.getElementById('notes-popup').style.display='none';
             loadSchedule();
             console.log('Dropped course:',courseId);
         }else{
-            alert(`Drop failed:${data.error}`);
-            console.error('Drop error:',data.error);
+            const data=await response.json();
+            alert(`Drop failed:${data.message}`);
+            console.error('Drop error:',data.message);
         }
     }catch(err){
         console.error('Drop failed:',err);